@@ -0,0 +1,3 @@
+ALTER TABLE "objects"
+DROP COLUMN "object_sha256",
+DROP COLUMN "image_sha256";
@@ -0,0 +1,3 @@
+ALTER TABLE "objects"
+ADD COLUMN "object_sha256" BYTEA NOT NULL DEFAULT '',
+ADD COLUMN "image_sha256" BYTEA NOT NULL DEFAULT '';
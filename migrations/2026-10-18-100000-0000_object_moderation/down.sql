@@ -0,0 +1,8 @@
+DROP INDEX "objects_verified_index";
+
+ALTER TABLE "objects" DROP CONSTRAINT objects_reviewed_by_fkey;
+
+ALTER TABLE "objects"
+DROP COLUMN "rejection_reason",
+DROP COLUMN "reviewed_by",
+DROP COLUMN "reviewed_at";
@@ -0,0 +1,11 @@
+ALTER TABLE "objects"
+ADD COLUMN "rejection_reason" VARCHAR(1024),
+ADD COLUMN "reviewed_by" UUID,
+ADD COLUMN "reviewed_at" TIMESTAMP;
+
+ALTER TABLE "objects"
+ADD FOREIGN KEY("reviewed_by") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE SET NULL;
+
+CREATE INDEX "objects_verified_index"
+ON "objects" ("verified");
@@ -0,0 +1,6 @@
+ALTER TABLE "object_scans" DROP CONSTRAINT object_scans_object_fkey;
+
+DROP TABLE "object_scans";
+
+ALTER TABLE "objects"
+DROP COLUMN "scan_status";
@@ -0,0 +1,19 @@
+ALTER TABLE "objects"
+ADD COLUMN "scan_status" SMALLINT NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS "object_scans" (
+	"id" SERIAL NOT NULL UNIQUE,
+	"object" UUID NOT NULL,
+	"object_sha256" BYTEA NOT NULL,
+	"scanned_at" TIMESTAMP NOT NULL DEFAULT now(),
+	"status" SMALLINT NOT NULL,
+	"details" VARCHAR(1024),
+	PRIMARY KEY("id")
+);
+
+CREATE INDEX "object_scans_object_index"
+ON "object_scans" ("object");
+
+ALTER TABLE "object_scans"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
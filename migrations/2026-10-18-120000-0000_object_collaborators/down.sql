@@ -0,0 +1,4 @@
+ALTER TABLE "object_collaborators" DROP CONSTRAINT object_collaborators_object_fkey;
+ALTER TABLE "object_collaborators" DROP CONSTRAINT object_collaborators_user_fkey;
+
+DROP TABLE "object_collaborators";
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS "object_collaborators" (
+	"object" UUID NOT NULL,
+	"user" UUID NOT NULL,
+	"role" SMALLINT NOT NULL,
+	"added_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object", "user")
+);
+
+CREATE INDEX "object_collaborators_user_index"
+ON "object_collaborators" ("user");
+
+ALTER TABLE "object_collaborators"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "object_collaborators"
+ADD FOREIGN KEY("user") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
@@ -0,0 +1,22 @@
+ALTER TABLE "objects"
+ADD COLUMN "encryption_key" BYTEA NOT NULL DEFAULT '';
+ALTER TABLE "objects"
+ADD COLUMN "encryption_iv" BYTEA NOT NULL DEFAULT '';
+
+-- wrapped keys cant be unwrapped here so only the old client chosen keys come back
+UPDATE "objects" SET
+	"encryption_key" = "object_keys"."wrapped_key",
+	"encryption_iv" = "object_keys"."iv"
+FROM "object_keys"
+WHERE "object_keys"."object" = "objects"."id"
+	AND "object_keys"."version" = "objects"."key_version"
+	AND NOT "object_keys"."wrapped";
+
+ALTER TABLE "objects" ALTER COLUMN "encryption_key" DROP DEFAULT;
+ALTER TABLE "objects" ALTER COLUMN "encryption_iv" DROP DEFAULT;
+
+ALTER TABLE "objects" DROP COLUMN "key_version";
+
+ALTER TABLE "object_keys" DROP CONSTRAINT object_keys_object_fkey;
+
+DROP TABLE "object_keys";
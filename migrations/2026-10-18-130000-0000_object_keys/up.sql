@@ -0,0 +1,28 @@
+CREATE TABLE IF NOT EXISTS "object_keys" (
+	"object" UUID NOT NULL,
+	"version" INTEGER NOT NULL,
+	"wrapped_key" BYTEA NOT NULL,
+	"iv" BYTEA NOT NULL,
+	-- keys chosen by clients before the server managed them are stored as is
+	"wrapped" BOOLEAN NOT NULL,
+	"created_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object", "version")
+);
+
+ALTER TABLE "object_keys"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+
+ALTER TABLE "objects"
+ADD COLUMN "key_version" INTEGER NOT NULL DEFAULT 0;
+
+INSERT INTO "object_keys" ("object", "version", "wrapped_key", "iv", "wrapped", "created_at")
+SELECT "id", 1, "encryption_key", "encryption_iv", false, "created_at"
+FROM "objects"
+WHERE length("encryption_key") > 0;
+
+UPDATE "objects" SET "key_version" = 1
+WHERE length("encryption_key") > 0;
+
+ALTER TABLE "objects" DROP COLUMN "encryption_key";
+ALTER TABLE "objects" DROP COLUMN "encryption_iv";
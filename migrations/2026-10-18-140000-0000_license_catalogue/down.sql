@@ -0,0 +1,30 @@
+DELETE FROM "licenses"
+WHERE NOT "custom"
+	AND "license" NOT IN (SELECT "license" FROM "objects");
+
+UPDATE "licenses" SET "text" = "spdx_id"
+WHERE NOT "custom";
+
+-- several licenses can end up with the same text now, point everything at the oldest one
+UPDATE "objects" SET "license" = "keep"."license"
+FROM "licenses" AS "duplicate", (
+	SELECT "text", min("license") AS "license" FROM "licenses" GROUP BY "text"
+) AS "keep"
+WHERE "objects"."license" = "duplicate"."license"
+	AND "duplicate"."text" = "keep"."text"
+	AND "duplicate"."license" <> "keep"."license";
+
+DELETE FROM "licenses"
+WHERE "license" NOT IN (SELECT min("license") FROM "licenses" GROUP BY "text");
+
+ALTER TABLE "licenses"
+DROP COLUMN "spdx_id",
+DROP COLUMN "name",
+DROP COLUMN "url",
+DROP COLUMN "custom",
+DROP COLUMN "allows_remix",
+DROP COLUMN "allows_commercial",
+DROP COLUMN "requires_attribution",
+DROP COLUMN "share_alike";
+
+ALTER TABLE "licenses" ADD UNIQUE ("text");
@@ -0,0 +1,66 @@
+ALTER TABLE "licenses" DROP CONSTRAINT licenses_text_key;
+
+-- every existing license was free text so they all become custom licenses
+ALTER TABLE "licenses"
+ADD COLUMN "spdx_id" VARCHAR(64) UNIQUE,
+ADD COLUMN "name" VARCHAR(128) NOT NULL DEFAULT '',
+ADD COLUMN "url" VARCHAR(256),
+ADD COLUMN "custom" BOOLEAN NOT NULL DEFAULT true,
+ADD COLUMN "allows_remix" BOOLEAN NOT NULL DEFAULT false,
+ADD COLUMN "allows_commercial" BOOLEAN NOT NULL DEFAULT false,
+ADD COLUMN "requires_attribution" BOOLEAN NOT NULL DEFAULT true,
+ADD COLUMN "share_alike" BOOLEAN NOT NULL DEFAULT false;
+
+UPDATE "licenses" SET "name" = left("text", 128);
+
+ALTER TABLE "licenses" ALTER COLUMN "name" DROP DEFAULT;
+ALTER TABLE "licenses" ALTER COLUMN "custom" DROP DEFAULT;
+ALTER TABLE "licenses" ALTER COLUMN "allows_remix" DROP DEFAULT;
+ALTER TABLE "licenses" ALTER COLUMN "allows_commercial" DROP DEFAULT;
+ALTER TABLE "licenses" ALTER COLUMN "requires_attribution" DROP DEFAULT;
+ALTER TABLE "licenses" ALTER COLUMN "share_alike" DROP DEFAULT;
+
+CREATE TEMPORARY TABLE "license_catalogue" (
+	"spdx_id" VARCHAR(64) NOT NULL,
+	"name" VARCHAR(128) NOT NULL,
+	"url" VARCHAR(256),
+	"allows_remix" BOOLEAN NOT NULL,
+	"allows_commercial" BOOLEAN NOT NULL,
+	"requires_attribution" BOOLEAN NOT NULL,
+	"share_alike" BOOLEAN NOT NULL
+);
+
+INSERT INTO "license_catalogue" VALUES
+	('CC0-1.0', 'Creative Commons Zero v1.0 Universal', 'https://spdx.org/licenses/CC0-1.0.html', true, true, false, false),
+	('CC-BY-4.0', 'Creative Commons Attribution 4.0 International', 'https://spdx.org/licenses/CC-BY-4.0.html', true, true, true, false),
+	('CC-BY-SA-4.0', 'Creative Commons Attribution Share Alike 4.0 International', 'https://spdx.org/licenses/CC-BY-SA-4.0.html', true, true, true, true),
+	('CC-BY-NC-4.0', 'Creative Commons Attribution Non Commercial 4.0 International', 'https://spdx.org/licenses/CC-BY-NC-4.0.html', true, false, true, false),
+	('CC-BY-NC-SA-4.0', 'Creative Commons Attribution Non Commercial Share Alike 4.0 International', 'https://spdx.org/licenses/CC-BY-NC-SA-4.0.html', true, false, true, true),
+	('CC-BY-ND-4.0', 'Creative Commons Attribution No Derivatives 4.0 International', 'https://spdx.org/licenses/CC-BY-ND-4.0.html', false, true, true, false),
+	('CC-BY-NC-ND-4.0', 'Creative Commons Attribution Non Commercial No Derivatives 4.0 International', 'https://spdx.org/licenses/CC-BY-NC-ND-4.0.html', false, false, true, false),
+	('MIT', 'MIT License', 'https://spdx.org/licenses/MIT.html', true, true, true, false),
+	('Apache-2.0', 'Apache License 2.0', 'https://spdx.org/licenses/Apache-2.0.html', true, true, true, false),
+	('MPL-2.0', 'Mozilla Public License 2.0', 'https://spdx.org/licenses/MPL-2.0.html', true, true, true, true),
+	('GPL-3.0-or-later', 'GNU General Public License v3.0 or later', 'https://spdx.org/licenses/GPL-3.0-or-later.html', true, true, true, true),
+	('LicenseRef-All-Rights-Reserved', 'All Rights Reserved', NULL, false, false, true, false);
+
+-- free text that was just an spdx id becomes the standard license
+UPDATE "licenses" SET
+	"spdx_id" = "license_catalogue"."spdx_id",
+	"name" = "license_catalogue"."name",
+	"url" = "license_catalogue"."url",
+	"custom" = false,
+	"allows_remix" = "license_catalogue"."allows_remix",
+	"allows_commercial" = "license_catalogue"."allows_commercial",
+	"requires_attribution" = "license_catalogue"."requires_attribution",
+	"share_alike" = "license_catalogue"."share_alike",
+	"text" = ''
+FROM "license_catalogue"
+WHERE "licenses"."text" = "license_catalogue"."spdx_id";
+
+INSERT INTO "licenses" ("text", "spdx_id", "name", "url", "custom", "allows_remix", "allows_commercial", "requires_attribution", "share_alike")
+SELECT '', "spdx_id", "name", "url", false, "allows_remix", "allows_commercial", "requires_attribution", "share_alike"
+FROM "license_catalogue"
+WHERE "spdx_id" NOT IN (SELECT "spdx_id" FROM "licenses" WHERE "spdx_id" IS NOT NULL);
+
+DROP TABLE "license_catalogue";
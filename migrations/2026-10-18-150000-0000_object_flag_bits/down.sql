@@ -0,0 +1,12 @@
+ALTER TABLE "objects"
+ADD COLUMN "flag_array" BOOLEAN[] NOT NULL DEFAULT '{}';
+
+UPDATE "objects" SET "flag_array" = ARRAY(
+	SELECT ("flags" >> bit) & 1 = 1
+	FROM generate_series(0, 3) AS bit
+	ORDER BY bit
+);
+
+ALTER TABLE "objects" DROP COLUMN "flags";
+ALTER TABLE "objects" RENAME COLUMN "flag_array" TO "flags";
+ALTER TABLE "objects" ALTER COLUMN "flags" DROP DEFAULT;
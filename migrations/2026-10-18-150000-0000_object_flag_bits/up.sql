@@ -0,0 +1,15 @@
+-- each position in the old array becomes the bit with the same index, see ObjectFlag
+-- postgres dosent allow subqueries in ALTER COLUMN ... USING so the column is rebuilt
+ALTER TABLE "objects"
+ADD COLUMN "flag_bits" INTEGER NOT NULL DEFAULT 0;
+
+UPDATE "objects" SET "flag_bits" = COALESCE((
+	SELECT bit_or(1 << (old."position" - 1)::INTEGER)
+	FROM unnest("flags") WITH ORDINALITY AS old("set", "position")
+	-- only positions that map to a known flag
+	WHERE old."set" AND old."position" <= 4
+), 0);
+
+ALTER TABLE "objects" DROP COLUMN "flags";
+ALTER TABLE "objects" RENAME COLUMN "flag_bits" TO "flags";
+ALTER TABLE "objects" ALTER COLUMN "flags" DROP DEFAULT;
@@ -0,0 +1,11 @@
+ALTER TABLE "collection_items" DROP CONSTRAINT collection_items_collection_fkey;
+ALTER TABLE "collection_items" DROP CONSTRAINT collection_items_object_fkey;
+ALTER TABLE "collections" DROP CONSTRAINT collections_owner_fkey;
+ALTER TABLE "favourites" DROP CONSTRAINT favourites_user_fkey;
+ALTER TABLE "favourites" DROP CONSTRAINT favourites_object_fkey;
+
+ALTER TABLE "objects" DROP COLUMN "favourite_count";
+
+DROP TABLE "collection_items";
+DROP TABLE "collections";
+DROP TABLE "favourites";
@@ -0,0 +1,56 @@
+CREATE TABLE IF NOT EXISTS "favourites" (
+	"user" UUID NOT NULL,
+	"object" UUID NOT NULL,
+	"added_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("user", "object")
+);
+
+CREATE INDEX "favourites_object_index"
+ON "favourites" ("object");
+
+CREATE TABLE IF NOT EXISTS "collections" (
+	"id" UUID NOT NULL UNIQUE,
+	"owner" UUID NOT NULL,
+	"name" VARCHAR(64) NOT NULL,
+	"description" VARCHAR(1024) NOT NULL,
+	"public" BOOLEAN NOT NULL,
+	"created_at" TIMESTAMP NOT NULL DEFAULT now(),
+	"updated_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("id")
+);
+
+CREATE INDEX "collections_owner_index"
+ON "collections" ("owner");
+
+CREATE TABLE IF NOT EXISTS "collection_items" (
+	"collection" UUID NOT NULL,
+	"object" UUID NOT NULL,
+	"position" INTEGER NOT NULL,
+	"added_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("collection", "object")
+);
+
+CREATE INDEX "collection_items_object_index"
+ON "collection_items" ("object");
+
+-- kept up to date when favourites are added or removed so search can sort on it
+ALTER TABLE "objects"
+ADD COLUMN "favourite_count" INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE "favourites"
+ADD FOREIGN KEY("user") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "favourites"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+
+ALTER TABLE "collections"
+ADD FOREIGN KEY("owner") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+
+ALTER TABLE "collection_items"
+ADD FOREIGN KEY("collection") REFERENCES "collections"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "collection_items"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
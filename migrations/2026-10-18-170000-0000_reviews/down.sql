@@ -0,0 +1,11 @@
+ALTER TABLE "review_reports" DROP CONSTRAINT review_reports_object_review_user_fkey;
+ALTER TABLE "review_reports" DROP CONSTRAINT review_reports_reporter_fkey;
+ALTER TABLE "reviews" DROP CONSTRAINT reviews_object_fkey;
+ALTER TABLE "reviews" DROP CONSTRAINT reviews_user_fkey;
+
+ALTER TABLE "objects"
+DROP COLUMN "rating_count",
+DROP COLUMN "rating_sum";
+
+DROP TABLE "review_reports";
+DROP TABLE "reviews";
@@ -0,0 +1,42 @@
+CREATE TABLE IF NOT EXISTS "reviews" (
+	"object" UUID NOT NULL,
+	"user" UUID NOT NULL,
+	"rating" SMALLINT NOT NULL CHECK ("rating" BETWEEN 1 AND 5),
+	"text" VARCHAR(2048) NOT NULL,
+	"reply" VARCHAR(2048),
+	"replied_at" TIMESTAMP,
+	"created_at" TIMESTAMP NOT NULL DEFAULT now(),
+	"updated_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object", "user")
+);
+
+CREATE INDEX "reviews_object_updated_at_index"
+ON "reviews" ("object", "updated_at");
+
+CREATE TABLE IF NOT EXISTS "review_reports" (
+	"object" UUID NOT NULL,
+	"review_user" UUID NOT NULL,
+	"reporter" UUID NOT NULL,
+	"reason" VARCHAR(1024) NOT NULL,
+	"created_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object", "review_user", "reporter")
+);
+
+-- the average is rating_sum / rating_count
+ALTER TABLE "objects"
+ADD COLUMN "rating_count" INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN "rating_sum" INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE "reviews"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "reviews"
+ADD FOREIGN KEY("user") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+
+ALTER TABLE "review_reports"
+ADD FOREIGN KEY("object", "review_user") REFERENCES "reviews"("object", "user")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "review_reports"
+ADD FOREIGN KEY("reporter") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
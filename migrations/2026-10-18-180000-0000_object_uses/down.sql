@@ -0,0 +1,7 @@
+ALTER TABLE "object_uses" DROP CONSTRAINT object_uses_object_fkey;
+ALTER TABLE "object_uses" DROP CONSTRAINT object_uses_user_fkey;
+
+ALTER TABLE "objects"
+DROP COLUMN "weekly_uses";
+
+DROP TABLE "object_uses";
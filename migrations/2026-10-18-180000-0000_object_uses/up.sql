@@ -0,0 +1,23 @@
+-- one row per user per object per day, "day" is days since the unix epoch
+CREATE TABLE IF NOT EXISTS "object_uses" (
+	"object" UUID NOT NULL,
+	"user" UUID NOT NULL,
+	"day" INTEGER NOT NULL,
+	"kind" SMALLINT NOT NULL,
+	"created_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object", "user", "day")
+);
+
+CREATE INDEX "object_uses_day_index"
+ON "object_uses" ("day");
+
+-- rolled up from object_uses by a background job
+ALTER TABLE "objects"
+ADD COLUMN "weekly_uses" INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE "object_uses"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "object_uses"
+ADD FOREIGN KEY("user") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
@@ -0,0 +1,13 @@
+DROP INDEX "users_username_trigram_index";
+
+DROP TRIGGER "tags_search_vector_update" ON "tags";
+DROP FUNCTION tags_search_vector_trigger();
+DROP TRIGGER "objects_search_vector_update" ON "objects";
+DROP FUNCTION objects_search_vector_trigger();
+
+ALTER TABLE "objects"
+DROP COLUMN "search_vector";
+
+DROP FUNCTION object_search_vector(UUID, TEXT, TEXT);
+
+DROP EXTENSION IF EXISTS pg_trgm;
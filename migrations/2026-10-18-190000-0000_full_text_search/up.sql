@@ -0,0 +1,52 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+-- the simple config dosent stem or drop stop words, names and tags are mostly not english anyway
+CREATE FUNCTION object_search_vector(object_id UUID, name TEXT, description TEXT)
+RETURNS tsvector AS $$
+	SELECT setweight(to_tsvector('simple', name), 'A')
+		|| setweight(to_tsvector('simple', coalesce((
+			SELECT string_agg(tag, ' ') FROM tags WHERE tags.object = object_id
+		), '')), 'B')
+		|| setweight(to_tsvector('simple', description), 'C');
+$$ LANGUAGE SQL STABLE;
+
+ALTER TABLE "objects"
+ADD COLUMN "search_vector" tsvector NOT NULL DEFAULT ''::tsvector;
+
+UPDATE "objects" SET "search_vector" = object_search_vector("id", "name", "description");
+
+CREATE INDEX "objects_search_vector_index"
+ON "objects" USING GIN ("search_vector");
+
+CREATE FUNCTION objects_search_vector_trigger() RETURNS trigger AS $$
+BEGIN
+	NEW.search_vector := object_search_vector(NEW.id, NEW.name, NEW.description);
+	RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "objects_search_vector_update"
+BEFORE INSERT OR UPDATE OF "name", "description" ON "objects"
+FOR EACH ROW EXECUTE FUNCTION objects_search_vector_trigger();
+
+-- tags live in their own table so changing them has to refresh the object
+CREATE FUNCTION tags_search_vector_trigger() RETURNS trigger AS $$
+BEGIN
+	IF TG_OP IN ('UPDATE', 'DELETE') THEN
+		UPDATE "objects" SET "search_vector" = object_search_vector("id", "name", "description")
+		WHERE "id" = OLD.object;
+	END IF;
+	IF TG_OP IN ('INSERT', 'UPDATE') THEN
+		UPDATE "objects" SET "search_vector" = object_search_vector("id", "name", "description")
+		WHERE "id" = NEW.object;
+	END IF;
+	RETURN NULL;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER "tags_search_vector_update"
+AFTER INSERT OR UPDATE OR DELETE ON "tags"
+FOR EACH ROW EXECUTE FUNCTION tags_search_vector_trigger();
+
+CREATE INDEX "users_username_trigram_index"
+ON "users" USING GIN ("username" gin_trgm_ops);
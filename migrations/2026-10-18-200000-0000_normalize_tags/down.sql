@@ -0,0 +1,2 @@
+-- the original case of tags is lost
+DROP INDEX "tags_tag_pattern_index";
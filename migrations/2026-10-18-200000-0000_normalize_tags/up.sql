@@ -0,0 +1,23 @@
+-- the same rule as normalize_tag, the class is what rusts trim counts as whitespace
+-- since \s depends on the database locale
+CREATE FUNCTION pg_temp.normalize_tag(TEXT) RETURNS TEXT
+AS $$ SELECT lower(regexp_replace($1,
+	'^[\u0009-\u000d\u0020\u0085\u00a0\u1680\u2000-\u200a\u2028\u2029\u202f\u205f\u3000]+|[\u0009-\u000d\u0020\u0085\u00a0\u1680\u2000-\u200a\u2028\u2029\u202f\u205f\u3000]+$',
+	'', 'g')) $$
+LANGUAGE SQL IMMUTABLE;
+
+-- tags that only differed by case or whitespace become the same tag
+DELETE FROM "tags" a
+USING "tags" b
+WHERE a."object" = b."object"
+	AND a."tag" <> b."tag"
+	AND pg_temp.normalize_tag(a."tag") = pg_temp.normalize_tag(b."tag")
+	AND (a."tag" <> pg_temp.normalize_tag(a."tag") AND (b."tag" = pg_temp.normalize_tag(b."tag") OR a."tag" > b."tag"));
+
+UPDATE "tags" SET "tag" = pg_temp.normalize_tag("tag") WHERE "tag" <> pg_temp.normalize_tag("tag");
+
+DROP FUNCTION pg_temp.normalize_tag(TEXT);
+
+-- for prefix searches, the primary key index cant be used for LIKE
+CREATE INDEX "tags_tag_pattern_index"
+ON "tags" ("tag" varchar_pattern_ops);
@@ -0,0 +1,4 @@
+ALTER TABLE "featured_objects" DROP CONSTRAINT featured_objects_object_fkey;
+ALTER TABLE "featured_objects" DROP CONSTRAINT featured_objects_added_by_fkey;
+
+DROP TABLE "featured_objects";
@@ -0,0 +1,21 @@
+-- objects picked by admins for the discover feeds, lowest position first
+CREATE TABLE IF NOT EXISTS "featured_objects" (
+	"object" UUID NOT NULL,
+	"position" INTEGER NOT NULL,
+	-- only featured between these times, either end can be left open
+	"starts_at" TIMESTAMP,
+	"ends_at" TIMESTAMP,
+	"added_by" UUID,
+	"created_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object")
+);
+
+CREATE INDEX "featured_objects_position_index"
+ON "featured_objects" ("position");
+
+ALTER TABLE "featured_objects"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "featured_objects"
+ADD FOREIGN KEY("added_by") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE SET NULL;
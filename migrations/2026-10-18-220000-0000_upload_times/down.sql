@@ -0,0 +1,3 @@
+ALTER TABLE "objects"
+DROP COLUMN "object_uploaded_at",
+DROP COLUMN "image_uploaded_at";
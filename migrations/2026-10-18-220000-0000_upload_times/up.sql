@@ -0,0 +1,10 @@
+-- when the package and image were last uploaded, updated_at also changes on metadata edits
+-- so it cant be used for Last-Modified
+ALTER TABLE "objects"
+ADD COLUMN "object_uploaded_at" TIMESTAMP NOT NULL DEFAULT now(),
+ADD COLUMN "image_uploaded_at" TIMESTAMP NOT NULL DEFAULT now();
+
+-- the closest thing existing objects have
+UPDATE "objects" SET
+	"object_uploaded_at" = "updated_at",
+	"image_uploaded_at" = "updated_at";
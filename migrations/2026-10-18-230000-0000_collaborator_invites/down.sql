@@ -0,0 +1,8 @@
+ALTER TABLE "collaborator_invites" DROP CONSTRAINT collaborator_invites_object_fkey;
+ALTER TABLE "collaborator_invites" DROP CONSTRAINT collaborator_invites_user_fkey;
+ALTER TABLE "ownership_transfers" DROP CONSTRAINT ownership_transfers_object_fkey;
+ALTER TABLE "ownership_transfers" DROP CONSTRAINT ownership_transfers_from_user_fkey;
+ALTER TABLE "ownership_transfers" DROP CONSTRAINT ownership_transfers_to_user_fkey;
+
+DROP TABLE "ownership_transfers";
+DROP TABLE "collaborator_invites";
@@ -0,0 +1,39 @@
+-- collaborators and new owners have to accept before they get the object
+CREATE TABLE IF NOT EXISTS "collaborator_invites" (
+	"object" UUID NOT NULL,
+	"user" UUID NOT NULL,
+	"role" SMALLINT NOT NULL,
+	"invited_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object", "user")
+);
+
+CREATE INDEX "collaborator_invites_user_index"
+ON "collaborator_invites" ("user");
+
+-- only one transfer can be pending per object, offering it again replaces it
+CREATE TABLE IF NOT EXISTS "ownership_transfers" (
+	"object" UUID NOT NULL,
+	"from_user" UUID NOT NULL,
+	"to_user" UUID NOT NULL,
+	"created_at" TIMESTAMP NOT NULL DEFAULT now(),
+	PRIMARY KEY("object")
+);
+
+CREATE INDEX "ownership_transfers_to_user_index"
+ON "ownership_transfers" ("to_user");
+
+ALTER TABLE "collaborator_invites"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "collaborator_invites"
+ADD FOREIGN KEY("user") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "ownership_transfers"
+ADD FOREIGN KEY("object") REFERENCES "objects"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "ownership_transfers"
+ADD FOREIGN KEY("from_user") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
+ALTER TABLE "ownership_transfers"
+ADD FOREIGN KEY("to_user") REFERENCES "users"("id")
+ON UPDATE CASCADE ON DELETE CASCADE;
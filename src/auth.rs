@@ -7,11 +7,13 @@ use axum::{
     response::Response,
 };
 use diesel::prelude::*;
+use diesel_async::AsyncPgConnection;
 use diesel_async::RunQueryDsl;
 use schema::tokens::dsl::*;
 use std::{sync::Arc, time::SystemTime};
 use uuid::Uuid;
 
+use crate::models::Permission;
 use crate::schema;
 
 pub async fn check_auth(
@@ -43,3 +45,19 @@ pub async fn check_auth(
     }
     Err(StatusCode::UNAUTHORIZED)
 }
+
+pub async fn has_permission(
+    conn: &mut AsyncPgConnection,
+    user_id: Uuid,
+    permission: Permission,
+) -> QueryResult<bool> {
+    let permisions = schema::users::table
+        .select(schema::users::permisions)
+        .filter(schema::users::id.eq(user_id))
+        .first::<Vec<Option<bool>>>(conn)
+        .await
+        .optional()?;
+    Ok(permisions
+        .and_then(|x| x.get(permission as usize).copied().flatten())
+        .unwrap_or(false))
+}
@@ -0,0 +1,654 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::ErrorCode;
+use crate::ErrorInfo;
+use crate::auth;
+use crate::models;
+use crate::models::*;
+use crate::objects::storage_used;
+use crate::objects::to_secs;
+use crate::schema::collaborator_invites;
+use crate::schema::object_collaborators;
+use crate::schema::objects;
+use crate::schema::ownership_transfers;
+use crate::schema::users;
+use axum::Extension;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::{Json, Router, routing::get, routing::post, routing::put};
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+const COLLABORATORS_ROUTE: &str = "/{object_type}/{uuid}/collaborators";
+const COLLABORATOR_ROUTE: &str = constcat::concat!(COLLABORATORS_ROUTE, "/{usr_id}");
+const INVITE_ROUTE: &str = "/{object_type}/{uuid}/invite";
+const OWNER_ROUTE: &str = "/{object_type}/{uuid}/owner";
+const OWNER_ACCEPT_ROUTE: &str = constcat::concat!(OWNER_ROUTE, "/accept");
+const INVITES_ROUTE: &str = "/invites";
+
+// the creator can do anything, everyone else needs a role of at least min_role
+pub async fn has_role(
+    conn: &mut AsyncPgConnection,
+    object_id: Uuid,
+    creator: Uuid,
+    user_id: Uuid,
+    min_role: CollaboratorRole,
+) -> QueryResult<bool> {
+    if creator == user_id {
+        return Ok(true);
+    }
+    let role = object_collaborators::table
+        .select(object_collaborators::role)
+        .filter(object_collaborators::object.eq(object_id))
+        .filter(object_collaborators::user.eq(user_id))
+        .first::<i16>(conn)
+        .await
+        .optional()?;
+    Ok(role
+        .and_then(CollaboratorRole::from_i16)
+        .is_some_and(|x| x >= min_role))
+}
+
+async fn find_object(
+    conn: &mut AsyncPgConnection,
+    object_type: models::ObjectType,
+    object_id: Uuid,
+) -> Result<Object, ApiError> {
+    objects::table
+        .select(Object::as_select())
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .first(conn)
+        .await
+        .optional()?
+        .ok_or_else(ApiError::not_found)
+}
+
+// find_object for transactions that depend on who owns the object,
+// so they cant race each other or an ownership transfer
+async fn lock_object(
+    conn: &mut AsyncPgConnection,
+    object_type: models::ObjectType,
+    object_id: Uuid,
+) -> Result<Object, ApiError> {
+    objects::table
+        .select(Object::as_select())
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .for_update()
+        .first(conn)
+        .await
+        .optional()?
+        .ok_or_else(ApiError::not_found)
+}
+
+fn owner_only() -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::FORBIDDEN,
+        Json(ErrorInfo {
+            error_code: ErrorCode::InsufficientPermissions,
+            error_message: Some("Only the owner can manage collaborators.".to_owned()),
+        }),
+    )
+}
+
+fn user_not_found() -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::NOT_FOUND,
+        Json(ErrorInfo {
+            error_code: ErrorCode::DosentExist,
+            error_message: Some("That user dosent exist.".to_owned()),
+        }),
+    )
+}
+
+#[derive(Serialize)]
+pub struct CollaboratorInfo {
+    pub user: Uuid,
+    pub username: String,
+    pub role: CollaboratorRole,
+    // invited but hasnt accepted yet
+    pub pending: bool,
+}
+
+pub async fn get_collaborators(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<Vec<CollaboratorInfo>>, ApiError> {
+    let mut conn = state.pool.get().await?;
+    let object = find_object(&mut conn, object_type, object_id).await?;
+
+    if !has_role(
+        &mut conn,
+        object_id,
+        object.creator,
+        user_id,
+        CollaboratorRole::Viewer,
+    )
+    .await?
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some(
+                    "Only the owner and collaborators can see collaborators.".to_owned(),
+                ),
+            }),
+        ));
+    }
+
+    let collaborators = object_collaborators::table
+        .inner_join(users::table)
+        .select((
+            object_collaborators::user,
+            users::username,
+            object_collaborators::role,
+        ))
+        .filter(object_collaborators::object.eq(object_id))
+        .order(object_collaborators::added_at.asc())
+        .load::<(Uuid, String, i16)>(&mut conn)
+        .await?;
+    let invited = collaborator_invites::table
+        .inner_join(users::table)
+        .select((
+            collaborator_invites::user,
+            users::username,
+            collaborator_invites::role,
+        ))
+        .filter(collaborator_invites::object.eq(object_id))
+        .order(collaborator_invites::invited_at.asc())
+        .load::<(Uuid, String, i16)>(&mut conn)
+        .await?;
+
+    Ok(Json(
+        collaborators
+            .into_iter()
+            .map(|x| (x, false))
+            .chain(invited.into_iter().map(|x| (x, true)))
+            .filter_map(|((user, username, role), pending)| {
+                Some(CollaboratorInfo {
+                    user,
+                    username,
+                    role: CollaboratorRole::from_i16(role)?,
+                    pending,
+                })
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct CollaboratorRequest {
+    role: CollaboratorRole,
+}
+
+// invites a collaborator or changes the role of an existing one
+// new collaborators have to accept the invite before they get access
+pub async fn set_collaborator(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id, usr_id)): Path<(models::ObjectType, Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<CollaboratorRequest>,
+) -> Result<StatusCode, ApiError> {
+    if usr_id == user_id {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some("You cant add yourself as a collaborator.".to_owned()),
+            }),
+        ));
+    }
+
+    let mut conn = state.pool.get().await?;
+    conn.transaction(|mut conn| {
+        async move {
+            let object = lock_object(conn, object_type, object_id).await?;
+            if object.creator != user_id {
+                return Err(owner_only());
+            }
+            if users::table
+                .count()
+                .filter(users::id.eq(usr_id))
+                .get_result::<i64>(&mut conn)
+                .await?
+                == 0
+            {
+                return Err(user_not_found());
+            }
+
+            if diesel::update(object_collaborators::table)
+                .filter(object_collaborators::object.eq(object_id))
+                .filter(object_collaborators::user.eq(usr_id))
+                .set(object_collaborators::role.eq(json.role as i16))
+                .execute(&mut conn)
+                .await?
+                == 1
+            {
+                return Ok(StatusCode::OK);
+            }
+
+            insert_into(collaborator_invites::table)
+                .values(CollaboratorInvite {
+                    object: object_id,
+                    user: usr_id,
+                    role: json.role as i16,
+                    invited_at: SystemTime::now(),
+                })
+                .on_conflict((collaborator_invites::object, collaborator_invites::user))
+                .do_update()
+                .set(collaborator_invites::role.eq(json.role as i16))
+                .execute(&mut conn)
+                .await?;
+            Ok(StatusCode::ACCEPTED)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+// the owner can remove anyone, collaborators can remove themselves
+// this also cancels or declines a pending invite
+pub async fn remove_collaborator(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id, usr_id)): Path<(models::ObjectType, Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    let object = find_object(&mut conn, object_type, object_id).await?;
+
+    if object.creator != user_id && usr_id != user_id {
+        return Err(owner_only());
+    }
+
+    let removed = diesel::delete(object_collaborators::table)
+        .filter(object_collaborators::object.eq(object_id))
+        .filter(object_collaborators::user.eq(usr_id))
+        .execute(&mut conn)
+        .await?
+        + diesel::delete(collaborator_invites::table)
+            .filter(collaborator_invites::object.eq(object_id))
+            .filter(collaborator_invites::user.eq(usr_id))
+            .execute(&mut conn)
+            .await?;
+    if removed == 0 {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: Some("That user isnt a collaborator.".to_owned()),
+            }),
+        ));
+    }
+
+    Ok(())
+}
+
+pub async fn accept_invite(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_object(&mut conn, object_type, object_id).await?;
+
+    conn.transaction(|mut conn| {
+        async move {
+            let Some(role) = diesel::delete(collaborator_invites::table)
+                .filter(collaborator_invites::object.eq(object_id))
+                .filter(collaborator_invites::user.eq(user_id))
+                .returning(collaborator_invites::role)
+                .get_result::<i16>(&mut conn)
+                .await
+                .optional()?
+            else {
+                return Err(ApiError::WithResponse(
+                    StatusCode::NOT_FOUND,
+                    Json(ErrorInfo {
+                        error_code: ErrorCode::DosentExist,
+                        error_message: Some(
+                            "You havent been invited to collaborate on this object.".to_owned(),
+                        ),
+                    }),
+                ));
+            };
+
+            insert_into(object_collaborators::table)
+                .values(ObjectCollaborator {
+                    object: object_id,
+                    user: user_id,
+                    role,
+                    added_at: SystemTime::now(),
+                })
+                .on_conflict((object_collaborators::object, object_collaborators::user))
+                .do_update()
+                .set(object_collaborators::role.eq(role))
+                .execute(&mut conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct TransferRequest {
+    user: Uuid,
+}
+
+fn not_enough_storage() -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(ErrorInfo {
+            error_code: ErrorCode::TooLarge,
+            error_message: Some(
+                "The new owner dosent have enough storage for this object.".to_owned(),
+            ),
+        }),
+    )
+}
+
+// whether the object fits in the users storage quota, or None if they dont exist
+async fn has_storage_for(
+    state: &AppState,
+    conn: &mut AsyncPgConnection,
+    object: &Object,
+    usr_id: Uuid,
+) -> QueryResult<Option<bool>> {
+    let Some(trust) = users::table
+        .select(users::trust)
+        .filter(users::id.eq(usr_id))
+        .first::<i32>(conn)
+        .await
+        .optional()?
+    else {
+        return Ok(None);
+    };
+    Ok(Some(
+        storage_used(usr_id, conn).await? + object.object_size + object.image_size
+            <= state.upload_limits.storage_quota(trust),
+    ))
+}
+
+// offers the object to another user, it only changes hands once they accept
+// offering it again replaces the previous offer
+pub async fn transfer_ownership(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<TransferRequest>,
+) -> Result<StatusCode, ApiError> {
+    if json.user == user_id {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some("You already own this object.".to_owned()),
+            }),
+        ));
+    }
+
+    let mut conn = state.pool.get().await?;
+    let state = &state;
+    conn.transaction(|mut conn| {
+        async move {
+            let object = lock_object(conn, object_type, object_id).await?;
+            if object.creator != user_id {
+                return Err(ApiError::forbidden(
+                    "Only the owner can transfer this object.",
+                ));
+            }
+
+            // checked again when they accept, this just fails early
+            match has_storage_for(state, conn, &object, json.user).await? {
+                None => return Err(user_not_found()),
+                Some(false) => return Err(not_enough_storage()),
+                Some(true) => {}
+            }
+
+            insert_into(ownership_transfers::table)
+                .values(OwnershipTransfer {
+                    object: object_id,
+                    from_user: user_id,
+                    to_user: json.user,
+                    created_at: SystemTime::now(),
+                })
+                .on_conflict(ownership_transfers::object)
+                .do_update()
+                .set((
+                    ownership_transfers::from_user.eq(user_id),
+                    ownership_transfers::to_user.eq(json.user),
+                    ownership_transfers::created_at.eq(SystemTime::now()),
+                ))
+                .execute(&mut conn)
+                .await?;
+            Ok(StatusCode::ACCEPTED)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+// the owner can take back an offer and the recipient can decline it
+pub async fn cancel_transfer(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_object(&mut conn, object_type, object_id).await?;
+
+    if diesel::delete(ownership_transfers::table)
+        .filter(ownership_transfers::object.eq(object_id))
+        .filter(
+            ownership_transfers::from_user
+                .eq(user_id)
+                .or(ownership_transfers::to_user.eq(user_id)),
+        )
+        .execute(&mut conn)
+        .await?
+        == 0
+    {
+        return Err(no_transfer());
+    }
+    Ok(())
+}
+
+fn no_transfer() -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::NOT_FOUND,
+        Json(ErrorInfo {
+            error_code: ErrorCode::DosentExist,
+            error_message: Some("There isnt a pending transfer of this object.".to_owned()),
+        }),
+    )
+}
+
+// the previous owner stays on as an editor
+pub async fn accept_transfer(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    let state = &state;
+    conn.transaction(|mut conn| {
+        async move {
+            let object = lock_object(conn, object_type, object_id).await?;
+
+            // an offer from someone who isnt the owner anymore is stale
+            if diesel::delete(ownership_transfers::table)
+                .filter(ownership_transfers::object.eq(object_id))
+                .filter(ownership_transfers::from_user.eq(object.creator))
+                .filter(ownership_transfers::to_user.eq(user_id))
+                .execute(&mut conn)
+                .await?
+                == 0
+            {
+                return Err(no_transfer());
+            }
+
+            // the object counts against the new owners quota from now on
+            if has_storage_for(state, conn, &object, user_id).await? != Some(true) {
+                return Err(not_enough_storage());
+            }
+
+            diesel::update(objects::table)
+                .filter(objects::id.eq(object_id))
+                .set(objects::creator.eq(user_id))
+                .execute(&mut conn)
+                .await?;
+
+            diesel::delete(object_collaborators::table)
+                .filter(object_collaborators::object.eq(object_id))
+                .filter(object_collaborators::user.eq(user_id))
+                .execute(&mut conn)
+                .await?;
+            diesel::delete(collaborator_invites::table)
+                .filter(collaborator_invites::object.eq(object_id))
+                .filter(collaborator_invites::user.eq(user_id))
+                .execute(&mut conn)
+                .await?;
+
+            insert_into(object_collaborators::table)
+                .values(ObjectCollaborator {
+                    object: object_id,
+                    user: object.creator,
+                    role: CollaboratorRole::Editor as i16,
+                    added_at: SystemTime::now(),
+                })
+                .execute(&mut conn)
+                .await?;
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[derive(Serialize)]
+pub struct InviteInfo {
+    pub object: Uuid,
+    pub object_type: i16,
+    pub name: String,
+    pub role: CollaboratorRole,
+    pub invited_at: u64,
+}
+
+#[derive(Serialize)]
+pub struct TransferInfo {
+    pub object: Uuid,
+    pub object_type: i16,
+    pub name: String,
+    pub from_user: Uuid,
+    pub from_username: String,
+    pub created_at: u64,
+}
+
+#[derive(Serialize)]
+pub struct PendingInvites {
+    pub collaborator: Vec<InviteInfo>,
+    pub ownership: Vec<TransferInfo>,
+}
+
+// everything waiting for the user to accept or decline, oldest first
+pub async fn get_invites(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<PendingInvites>, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let collaborator = collaborator_invites::table
+        .inner_join(objects::table)
+        .select((
+            objects::id,
+            objects::object_type,
+            objects::name,
+            collaborator_invites::role,
+            collaborator_invites::invited_at,
+        ))
+        .filter(collaborator_invites::user.eq(user_id))
+        .order(collaborator_invites::invited_at.asc())
+        .load::<(Uuid, i16, String, i16, SystemTime)>(&mut conn)
+        .await?;
+
+    let ownership = ownership_transfers::table
+        .inner_join(objects::table)
+        .inner_join(users::table.on(users::id.eq(ownership_transfers::from_user)))
+        .select((
+            objects::id,
+            objects::object_type,
+            objects::name,
+            ownership_transfers::from_user,
+            users::username,
+            ownership_transfers::created_at,
+        ))
+        .filter(ownership_transfers::to_user.eq(user_id))
+        .order(ownership_transfers::created_at.asc())
+        .load::<(Uuid, i16, String, Uuid, String, SystemTime)>(&mut conn)
+        .await?;
+
+    Ok(Json(PendingInvites {
+        collaborator: collaborator
+            .into_iter()
+            .filter_map(|(object, object_type, name, role, invited_at)| {
+                Some(InviteInfo {
+                    object,
+                    object_type,
+                    name,
+                    role: CollaboratorRole::from_i16(role)?,
+                    invited_at: to_secs(invited_at),
+                })
+            })
+            .collect(),
+        ownership: ownership
+            .into_iter()
+            .map(
+                |(object, object_type, name, from_user, from_username, created_at)| TransferInfo {
+                    object,
+                    object_type,
+                    name,
+                    from_user,
+                    from_username,
+                    created_at: to_secs(created_at),
+                },
+            )
+            .collect(),
+    }))
+}
+
+pub fn collaborators_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(COLLABORATORS_ROUTE, get(get_collaborators))
+        .route(
+            COLLABORATOR_ROUTE,
+            put(set_collaborator).delete(remove_collaborator),
+        )
+        .route(INVITE_ROUTE, post(accept_invite))
+        .route(
+            OWNER_ROUTE,
+            post(transfer_ownership).delete(cancel_transfer),
+        )
+        .route(OWNER_ACCEPT_ROUTE, post(accept_transfer))
+        .route(INVITES_ROUTE, get(get_invites))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,652 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::ErrorCode;
+use crate::ErrorInfo;
+use crate::auth;
+use crate::auth::has_permission;
+use crate::models;
+use crate::models::*;
+use crate::objects::find_visible_object;
+use crate::objects::to_secs;
+use crate::objects::visible_objects_filter;
+use crate::schema::collection_items;
+use crate::schema::collections;
+use crate::schema::favourites;
+use crate::schema::object_collaborators;
+use crate::schema::objects;
+use crate::search::Cursor;
+use crate::search::Page;
+use axum::Extension;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::response::IntoResponse;
+use axum::{Json, Router, routing::get, routing::put};
+use diesel::dsl::max;
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+const FAVOURITE_ROUTE: &str = "/{object_type}/{uuid}/favourite";
+const FAVOURITES_ROUTE: &str = "/favourites";
+const COLLECTIONS_ROUTE: &str = "/collections";
+const COLLECTION_ID_ROUTE: &str = constcat::concat!(COLLECTIONS_ROUTE, "/{id}");
+const COLLECTION_ITEM_ROUTE: &str = constcat::concat!(COLLECTION_ID_ROUTE, "/items/{object_id}");
+const COLLECTION_ORDER_ROUTE: &str = constcat::concat!(COLLECTION_ID_ROUTE, "/order");
+const USER_COLLECTIONS_ROUTE: &str = "/user/{usr_id}/collections";
+
+const MAX_COLLECTIONS: i64 = 100;
+const MAX_COLLECTION_ITEMS: i64 = 1000;
+// what favourites cursors are tagged with so they cant be used for other listings
+const FAVOURITES_ORDER: &str = "favourites";
+
+// an object in someones favourites or a collection
+#[derive(Serialize)]
+pub struct SavedObject {
+    pub id: Uuid,
+    pub name: String,
+    pub object_type: i16,
+    pub added_at: u64,
+}
+
+impl From<(Uuid, String, i16, SystemTime)> for SavedObject {
+    fn from((id, name, object_type, added_at): (Uuid, String, i16, SystemTime)) -> Self {
+        SavedObject {
+            id,
+            name,
+            object_type,
+            added_at: to_secs(added_at),
+        }
+    }
+}
+
+// favourites are private, the count on each object is public
+pub async fn add_favourite(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_visible_object(&state, &mut conn, Some(object_type), object_id, user_id).await?;
+
+    conn.transaction(|mut conn| {
+        async move {
+            // favouriting something twice shouldnt count twice
+            if insert_into(favourites::table)
+                .values(Favourite {
+                    user: user_id,
+                    object: object_id,
+                    added_at: SystemTime::now(),
+                })
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await?
+                == 1
+            {
+                diesel::update(objects::table)
+                    .filter(objects::id.eq(object_id))
+                    .set(objects::favourite_count.eq(objects::favourite_count + 1))
+                    .execute(&mut conn)
+                    .await?;
+            }
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+pub async fn remove_favourite(
+    State(state): State<Arc<AppState>>,
+    Path((_, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    conn.transaction(|mut conn| {
+        async move {
+            if diesel::delete(favourites::table)
+                .filter(favourites::user.eq(user_id))
+                .filter(favourites::object.eq(object_id))
+                .execute(&mut conn)
+                .await?
+                == 0
+            {
+                return Err(ApiError::not_found());
+            }
+            diesel::update(objects::table)
+                .filter(objects::id.eq(object_id))
+                .set(objects::favourite_count.eq(objects::favourite_count - 1))
+                .execute(&mut conn)
+                .await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FavouritesQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct Favourites {
+    pub objects: Vec<SavedObject>,
+    // pass back as the cursor parameter to get the next page, missing on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+// newest first, leaves out anything the user cant see anymore
+pub async fn get_favourites(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Uuid>,
+    Query(query): Query<FavouritesQuery>,
+) -> Result<Json<Favourites>, ApiError> {
+    let page = Page::new(query.limit, query.cursor.as_deref(), 1)?;
+    let mut conn = state.pool.get().await?;
+    let verifier = has_permission(&mut conn, user_id, Permission::Verifier).await?;
+
+    let mut query = favourites::table
+        .inner_join(objects::table)
+        .select((
+            objects::id,
+            objects::name,
+            objects::object_type,
+            favourites::added_at,
+        ))
+        .filter(favourites::user.eq(user_id))
+        .order((favourites::added_at.desc(), objects::id.asc()))
+        .limit(page.limit + 1)
+        .into_boxed();
+    visible_objects_filter!(query, user_id, verifier, state.hide_unverified);
+    if let Some(cursor) = &page.cursor {
+        let added_at: SystemTime = cursor.key_for(FAVOURITES_ORDER)?;
+        query = query.filter(
+            favourites::added_at.lt(added_at).or(favourites::added_at
+                .eq(added_at)
+                .and(objects::id.gt(cursor.id))),
+        );
+    }
+    let mut saved = query
+        .load::<(Uuid, String, i16, SystemTime)>(&mut conn)
+        .await?;
+
+    // one extra row is loaded to tell if theres another page
+    let next_cursor = if saved.len() as i64 > page.limit {
+        saved.truncate(page.limit as usize);
+        saved
+            .last()
+            .map(|(id, _, _, added_at)| Cursor::encode(FAVOURITES_ORDER, *id, added_at))
+    } else {
+        None
+    };
+
+    Ok(Json(Favourites {
+        objects: saved.into_iter().map(SavedObject::from).collect(),
+        next_cursor,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct CollectionInfo {
+    pub id: Uuid,
+    pub owner: Uuid,
+    pub name: String,
+    pub description: String,
+    pub public: bool,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl From<Collection> for CollectionInfo {
+    fn from(value: Collection) -> Self {
+        CollectionInfo {
+            id: value.id,
+            owner: value.owner,
+            name: value.name,
+            description: value.description,
+            public: value.public,
+            created_at: to_secs(value.created_at),
+            updated_at: to_secs(value.updated_at),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CollectionContents {
+    #[serde(flatten)]
+    pub info: CollectionInfo,
+    // in the order the owner put them
+    pub items: Vec<SavedObject>,
+}
+
+fn check_collection_fields(name: Option<&str>, description: Option<&str>) -> Result<(), ApiError> {
+    if name.is_some_and(|x| x.is_empty() || x.len() > 64)
+        || description.is_some_and(|x| x.len() > 1024)
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::BadRequestLength,
+                error_message: Some(String::from(
+                    "Name or description was wrong length. This shouldnt happen",
+                )),
+            }),
+        ));
+    }
+    Ok(())
+}
+
+// private collections are only visible to their owner
+async fn find_collection(
+    conn: &mut AsyncPgConnection,
+    collection_id: Uuid,
+    user_id: Uuid,
+) -> Result<Collection, ApiError> {
+    collections::table
+        .select(Collection::as_select())
+        .filter(collections::id.eq(collection_id))
+        .first::<Collection>(conn)
+        .await
+        .optional()?
+        .filter(|x| x.public || x.owner == user_id)
+        .ok_or_else(ApiError::not_found)
+}
+
+async fn find_own_collection(
+    conn: &mut AsyncPgConnection,
+    collection_id: Uuid,
+    user_id: Uuid,
+) -> Result<Collection, ApiError> {
+    let collection = find_collection(conn, collection_id, user_id).await?;
+    if collection.owner != user_id {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some("Only the owner can change this collection.".to_owned()),
+            }),
+        ));
+    }
+    Ok(collection)
+}
+
+async fn touch_collection(conn: &mut AsyncPgConnection, collection_id: Uuid) -> QueryResult<()> {
+    diesel::update(collections::table)
+        .filter(collections::id.eq(collection_id))
+        .set(collections::updated_at.eq(SystemTime::now()))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+pub async fn get_own_collections(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<Vec<CollectionInfo>>, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let owned = collections::table
+        .select(Collection::as_select())
+        .filter(collections::owner.eq(user_id))
+        .order(collections::created_at.asc())
+        .load::<Collection>(&mut conn)
+        .await?;
+
+    Ok(Json(owned.into_iter().map(CollectionInfo::from).collect()))
+}
+
+// only public collections unless the user is looking at their own
+pub async fn get_user_collections(
+    State(state): State<Arc<AppState>>,
+    Path(usr_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<Vec<CollectionInfo>>, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let mut query = collections::table
+        .select(Collection::as_select())
+        .filter(collections::owner.eq(usr_id))
+        .order(collections::created_at.asc())
+        .into_boxed();
+    if usr_id != user_id {
+        query = query.filter(collections::public.eq(true));
+    }
+
+    Ok(Json(
+        query
+            .load::<Collection>(&mut conn)
+            .await?
+            .into_iter()
+            .map(CollectionInfo::from)
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct NewCollection {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    public: bool,
+}
+
+#[derive(Serialize)]
+pub struct CreatedCollection {
+    pub id: Uuid,
+}
+
+pub async fn create_collection(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<NewCollection>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_collection_fields(Some(&json.name), Some(&json.description))?;
+    let mut conn = state.pool.get().await?;
+
+    if collections::table
+        .count()
+        .filter(collections::owner.eq(user_id))
+        .get_result::<i64>(&mut conn)
+        .await?
+        >= MAX_COLLECTIONS
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::TooLarge,
+                error_message: Some(format!(
+                    "You can have at most {} collections.",
+                    MAX_COLLECTIONS
+                )),
+            }),
+        ));
+    }
+
+    let id = Uuid::new_v4();
+    insert_into(collections::table)
+        .values(Collection {
+            id,
+            owner: user_id,
+            name: json.name,
+            description: json.description,
+            public: json.public,
+            created_at: SystemTime::now(),
+            updated_at: SystemTime::now(),
+        })
+        .execute(&mut conn)
+        .await?;
+
+    Ok((StatusCode::CREATED, Json(CreatedCollection { id })))
+}
+
+pub async fn get_collection(
+    State(state): State<Arc<AppState>>,
+    Path(collection_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<CollectionContents>, ApiError> {
+    let mut conn = state.pool.get().await?;
+    let collection = find_collection(&mut conn, collection_id, user_id).await?;
+
+    let verifier = has_permission(&mut conn, user_id, Permission::Verifier).await?;
+
+    // items the user cant see anymore are left out
+    let mut query = collection_items::table
+        .inner_join(objects::table)
+        .select((
+            objects::id,
+            objects::name,
+            objects::object_type,
+            collection_items::added_at,
+        ))
+        .filter(collection_items::collection.eq(collection_id))
+        .order(collection_items::position.asc())
+        .into_boxed();
+    visible_objects_filter!(query, user_id, verifier, state.hide_unverified);
+    let saved = query
+        .load::<(Uuid, String, i16, SystemTime)>(&mut conn)
+        .await?;
+
+    Ok(Json(CollectionContents {
+        info: collection.into(),
+        items: saved.into_iter().map(SavedObject::from).collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct CollectionPatch {
+    name: Option<String>,
+    description: Option<String>,
+    public: Option<bool>,
+}
+
+pub async fn update_collection(
+    State(state): State<Arc<AppState>>,
+    Path(collection_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<CollectionPatch>,
+) -> Result<(), ApiError> {
+    check_collection_fields(json.name.as_deref(), json.description.as_deref())?;
+    let mut conn = state.pool.get().await?;
+    let mut collection = find_own_collection(&mut conn, collection_id, user_id).await?;
+
+    if let Some(name) = json.name {
+        collection.name = name;
+    }
+    if let Some(description) = json.description {
+        collection.description = description;
+    }
+    if let Some(public) = json.public {
+        collection.public = public;
+    }
+    collection.updated_at = SystemTime::now();
+
+    diesel::update(collections::table)
+        .filter(collections::id.eq(collection_id))
+        .set(collection)
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn delete_collection(
+    State(state): State<Arc<AppState>>,
+    Path(collection_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_own_collection(&mut conn, collection_id, user_id).await?;
+
+    diesel::delete(collections::table)
+        .filter(collections::id.eq(collection_id))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+// new items go at the end, adding an item thats already there does nothing
+pub async fn add_collection_item(
+    State(state): State<Arc<AppState>>,
+    Path((collection_id, object_id)): Path<(Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_own_collection(&mut conn, collection_id, user_id).await?;
+    find_visible_object(&state, &mut conn, None, object_id, user_id).await?;
+
+    conn.transaction(|mut conn| {
+        async move {
+            // locks the collection so concurrent adds dont get the same position
+            collections::table
+                .select(collections::id)
+                .filter(collections::id.eq(collection_id))
+                .for_update()
+                .first::<Uuid>(&mut conn)
+                .await?;
+
+            if collection_items::table
+                .count()
+                .filter(collection_items::collection.eq(collection_id))
+                .get_result::<i64>(&mut conn)
+                .await?
+                >= MAX_COLLECTION_ITEMS
+            {
+                return Err(ApiError::WithResponse(
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorInfo {
+                        error_code: ErrorCode::TooLarge,
+                        error_message: Some(format!(
+                            "Collections can have at most {} items.",
+                            MAX_COLLECTION_ITEMS
+                        )),
+                    }),
+                ));
+            }
+
+            let position = collection_items::table
+                .select(max(collection_items::position))
+                .filter(collection_items::collection.eq(collection_id))
+                .first::<Option<i32>>(&mut conn)
+                .await?
+                .map_or(0, |x| x + 1);
+
+            insert_into(collection_items::table)
+                .values(CollectionItem {
+                    collection: collection_id,
+                    object: object_id,
+                    position,
+                    added_at: SystemTime::now(),
+                })
+                .on_conflict_do_nothing()
+                .execute(&mut conn)
+                .await?;
+
+            touch_collection(conn, collection_id).await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+pub async fn remove_collection_item(
+    State(state): State<Arc<AppState>>,
+    Path((collection_id, object_id)): Path<(Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_own_collection(&mut conn, collection_id, user_id).await?;
+
+    if diesel::delete(collection_items::table)
+        .filter(collection_items::collection.eq(collection_id))
+        .filter(collection_items::object.eq(object_id))
+        .execute(&mut conn)
+        .await?
+        == 0
+    {
+        return Err(ApiError::not_found());
+    }
+    touch_collection(&mut conn, collection_id).await?;
+
+    Ok(())
+}
+
+// takes every item in the collection in its new order
+pub async fn reorder_collection(
+    State(state): State<Arc<AppState>>,
+    Path(collection_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+    Json(order): Json<Vec<Uuid>>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_own_collection(&mut conn, collection_id, user_id).await?;
+
+    conn.transaction(|mut conn| {
+        async move {
+            let items: HashSet<Uuid> = collection_items::table
+                .select(collection_items::object)
+                .filter(collection_items::collection.eq(collection_id))
+                .for_update()
+                .load::<Uuid>(&mut conn)
+                .await?
+                .into_iter()
+                .collect();
+
+            if order.len() != items.len()
+                || order.iter().collect::<HashSet<_>>().len() != items.len()
+                || !order.iter().all(|x| items.contains(x))
+            {
+                return Err(ApiError::WithResponse(
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorInfo {
+                        error_code: ErrorCode::InvalidRequest,
+                        error_message: Some(
+                            "The new order must contain every item in the collection once."
+                                .to_owned(),
+                        ),
+                    }),
+                ));
+            }
+
+            for (position, object_id) in order.into_iter().enumerate() {
+                diesel::update(collection_items::table)
+                    .filter(collection_items::collection.eq(collection_id))
+                    .filter(collection_items::object.eq(object_id))
+                    .set(collection_items::position.eq(position as i32))
+                    .execute(&mut conn)
+                    .await?;
+            }
+
+            touch_collection(conn, collection_id).await?;
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+pub fn collections_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(FAVOURITE_ROUTE, put(add_favourite).delete(remove_favourite))
+        .route(FAVOURITES_ROUTE, get(get_favourites))
+        .route(
+            COLLECTIONS_ROUTE,
+            get(get_own_collections).post(create_collection),
+        )
+        .route(
+            COLLECTION_ID_ROUTE,
+            get(get_collection)
+                .patch(update_collection)
+                .delete(delete_collection),
+        )
+        .route(
+            COLLECTION_ITEM_ROUTE,
+            put(add_collection_item).delete(remove_collection_item),
+        )
+        .route(COLLECTION_ORDER_ROUTE, put(reorder_collection))
+        .route(USER_COLLECTIONS_ROUTE, get(get_user_collections))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,146 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::auth;
+use crate::models::*;
+use crate::objects::ObjectSummary;
+use crate::schema::object_collaborators;
+use crate::schema::objects;
+use crate::schema::users;
+use crate::search::Filter;
+use crate::search::Page;
+use crate::search::Sort;
+use crate::search::search_objects;
+use axum::Extension;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::middleware;
+use axum::{Json, Router, routing::get};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// the last segment is the search field of an object type, e.g. /user/{usr_id}/worlds
+const CREATOR_OBJECTS_ROUTE: &str = "/user/{usr_id}/{object_types}";
+
+// how many of each object type a user has published, keyed by search field
+// only counts what the user asking could see in the listings below
+pub async fn object_counts(
+    state: &AppState,
+    conn: &mut AsyncPgConnection,
+    creator: Uuid,
+    user_id: Uuid,
+) -> QueryResult<BTreeMap<&'static str, i64>> {
+    let mut query = objects::table
+        .group_by(objects::object_type)
+        .select((objects::object_type, count_star()))
+        .filter(objects::creator.eq(creator))
+        .filter(objects::scan_status.ne(ScanStatus::Quarantined as i16))
+        .into_boxed();
+
+    if creator != user_id {
+        let collaborating = objects::id.eq_any(
+            object_collaborators::table
+                .select(object_collaborators::object)
+                .filter(object_collaborators::user.eq(user_id)),
+        );
+        query = query.filter(
+            objects::publicity
+                .eq(Publicity::Public as i16)
+                .or(collaborating),
+        );
+        if state.hide_unverified {
+            query = query.filter(objects::verified.or(collaborating));
+        }
+    }
+
+    let counts = query.load::<(i16, i64)>(conn).await?;
+    Ok(ObjectType::ALL
+        .into_iter()
+        .map(|object_type| {
+            let count = counts
+                .iter()
+                .find(|(x, _)| *x == object_type as i16)
+                .map_or(0, |(_, count)| *count);
+            (object_type.info().search_field, count)
+        })
+        .collect())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CreatorObjectsQuery {
+    // newest first if not set
+    sort: Option<Sort>,
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreatorObjects {
+    objects: Vec<ObjectSummary>,
+    // pass back as the cursor parameter to get the next page, missing on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+}
+
+// everything a user has published of one type, the same way search would show it
+pub async fn get_creator_objects(
+    State(state): State<Arc<AppState>>,
+    Path((usr_id, object_types)): Path<(Uuid, String)>,
+    Extension(user_id): Extension<Uuid>,
+    Query(query): Query<CreatorObjectsQuery>,
+) -> Result<Json<CreatorObjects>, ApiError> {
+    let object_type = ObjectType::ALL
+        .into_iter()
+        .find(|x| x.info().search_field == object_types)
+        .ok_or_else(ApiError::not_found)?;
+    let page = Page::new(query.limit, query.cursor.as_deref(), 1)?;
+
+    let mut conn = state.pool.get().await?;
+
+    if users::table
+        .count()
+        .filter(users::id.eq(usr_id))
+        .get_result::<i64>(&mut conn)
+        .await?
+        == 0
+    {
+        return Err(ApiError::not_found());
+    }
+
+    let mut filters = vec![Filter::Creator(usr_id)];
+    filters.extend(query.sort.map(Filter::SortBy));
+
+    let (objects, next_cursor) = search_objects(
+        object_type,
+        &filters,
+        "",
+        user_id,
+        state.hide_unverified,
+        &page,
+        &mut conn,
+    )
+    .await?;
+
+    Ok(Json(CreatorObjects {
+        objects,
+        next_cursor,
+    }))
+}
+
+pub fn creators_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(CREATOR_OBJECTS_ROUTE, get(get_creator_objects))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,390 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::ErrorCode;
+use crate::ErrorInfo;
+use crate::auth;
+use crate::auth::has_permission;
+use crate::licenses::LicenseInfo;
+use crate::models;
+use crate::models::*;
+use crate::objects::ObjectSummary;
+use crate::objects::load_tags;
+use crate::objects::to_secs;
+use crate::schema::favourites;
+use crate::schema::featured_objects;
+use crate::schema::licenses;
+use crate::schema::object_uses;
+use crate::schema::objects;
+use crate::schema::users;
+use crate::search::from_secs;
+use crate::usage::today;
+use axum::Extension;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header;
+use axum::middleware;
+use axum::response::IntoResponse;
+use axum::{Json, Router, routing::get, routing::put};
+use bytes::Bytes;
+use diesel::dsl::IntervalDsl;
+use diesel::dsl::now;
+use diesel::dsl::sql;
+use diesel::insert_into;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::Double;
+use diesel::sql_types::Integer;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+const DISCOVER_ROUTE: &str = "/discover/{object_type}";
+const FEATURED_ROUTE: &str = "/featured";
+const FEATURED_OBJECT_ROUTE: &str = constcat::concat!(FEATURED_ROUTE, "/{uuid}");
+
+const FEED_LENGTH: i64 = 20;
+const CACHE_TTL: Duration = Duration::from_secs(60);
+// a use counts half as much every 2 days
+const TRENDING_HALF_LIFE_DAYS: f64 = 2.0;
+// a favourite counts as much as this many uses on the same day
+const FAVOURITE_WEIGHT: f64 = 3.0;
+
+// feeds only contain objects everyone can see, so theyre the same for every user
+// and can be cached for each type, indexed by ObjectType
+pub struct FeedCache {
+    feeds: [Mutex<Option<(Instant, Bytes)>>; ObjectType::ALL.len()],
+}
+
+impl FeedCache {
+    pub fn new() -> FeedCache {
+        FeedCache {
+            feeds: std::array::from_fn(|_| Mutex::new(None)),
+        }
+    }
+
+    async fn clear(&self) {
+        for feeds in &self.feeds {
+            *feeds.lock().await = None;
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct DiscoverFeeds {
+    // picked by admins, in the order they set
+    pub featured: Vec<ObjectSummary>,
+    // most used and favourited recently
+    pub trending: Vec<ObjectSummary>,
+    // newest first
+    pub new: Vec<ObjectSummary>,
+}
+
+async fn require_admin(conn: &mut AsyncPgConnection, user_id: Uuid) -> Result<(), ApiError> {
+    if has_permission(conn, user_id, Permission::Admin).await? {
+        return Ok(());
+    }
+    Err(ApiError::forbidden(
+        "You do not have permission to manage featured objects.",
+    ))
+}
+
+// ids of objects that can be in a feed, verified and public and not quarantined
+fn feed_objects(
+    object_type: ObjectType,
+) -> objects::BoxedQuery<'static, Pg, diesel::sql_types::Uuid> {
+    objects::table
+        .select(objects::id)
+        .filter(objects::object_type.eq(object_type as i16))
+        .filter(objects::verified.eq(true))
+        .filter(objects::publicity.eq(Publicity::Public as i16))
+        .filter(objects::scan_status.ne(ScanStatus::Quarantined as i16))
+        .limit(FEED_LENGTH)
+        .into_boxed()
+}
+
+// loads summaries for a feed, keeping the order of the ids
+async fn load_summaries(
+    conn: &mut AsyncPgConnection,
+    ids: Vec<Uuid>,
+) -> QueryResult<Vec<ObjectSummary>> {
+    let mut tags = load_tags(conn, &ids).await?;
+    let mut summaries: HashMap<Uuid, ObjectSummary> = objects::table
+        .inner_join(licenses::table)
+        .inner_join(users::table.on(users::id.eq(objects::creator)))
+        .select((
+            Object::as_select(),
+            LicenseInfo::as_select(),
+            users::username,
+        ))
+        .filter(objects::id.eq_any(&ids))
+        .load::<(Object, LicenseInfo, String)>(conn)
+        .await?
+        .into_iter()
+        .map(|(object, license, creator_username)| {
+            let tags = tags.remove(&object.id).unwrap_or_default();
+            (
+                object.id,
+                ObjectSummary::new(&object, creator_username, license, tags),
+            )
+        })
+        .collect();
+    Ok(ids.iter().filter_map(|id| summaries.remove(id)).collect())
+}
+
+async fn load_feeds(
+    conn: &mut AsyncPgConnection,
+    object_type: ObjectType,
+) -> QueryResult<DiscoverFeeds> {
+    let featured = feed_objects(object_type)
+        .filter(
+            objects::id.eq_any(
+                featured_objects::table
+                    .select(featured_objects::object)
+                    .filter(
+                        featured_objects::starts_at
+                            .is_null()
+                            .or(featured_objects::starts_at.le(now)),
+                    )
+                    .filter(
+                        featured_objects::ends_at
+                            .is_null()
+                            .or(featured_objects::ends_at.gt(now)),
+                    ),
+            ),
+        )
+        .order((
+            sql::<Integer>(
+                "(SELECT featured_objects.position FROM featured_objects \
+                WHERE featured_objects.object = objects.id)",
+            )
+            .asc(),
+            objects::id.asc(),
+        ))
+        .load::<Uuid>(conn)
+        .await?;
+
+    // each days uses and each favourite from the last week, worth less the older they are
+    let trending = feed_objects(object_type)
+        .filter(
+            objects::id
+                .eq_any(object_uses::table.select(object_uses::object))
+                .or(objects::id.eq_any(
+                    favourites::table
+                        .select(favourites::object)
+                        .filter(favourites::added_at.gt(now - 7.days())),
+                )),
+        )
+        .order((
+            sql::<Double>("COALESCE((SELECT SUM(POWER(0.5, (")
+                .bind::<Integer, _>(today())
+                .sql(" - object_uses.day) / ")
+                .bind::<Double, _>(TRENDING_HALF_LIFE_DAYS)
+                .sql(
+                    ")) FROM object_uses WHERE object_uses.object = objects.id), 0) + \
+                COALESCE((SELECT SUM(POWER(0.5, \
+                EXTRACT(EPOCH FROM now() - favourites.added_at)::FLOAT8 / 86400 / ",
+                )
+                .bind::<Double, _>(TRENDING_HALF_LIFE_DAYS)
+                .sql(
+                    ")) FROM favourites WHERE favourites.object = objects.id \
+                AND favourites.added_at > now() - INTERVAL '7 days'), 0) * ",
+                )
+                .bind::<Double, _>(FAVOURITE_WEIGHT)
+                .desc(),
+            objects::id.asc(),
+        ))
+        .load::<Uuid>(conn)
+        .await?;
+
+    let new = feed_objects(object_type)
+        .order((objects::created_at.desc(), objects::id.asc()))
+        .load::<Uuid>(conn)
+        .await?;
+
+    Ok(DiscoverFeeds {
+        featured: load_summaries(conn, featured).await?,
+        trending: load_summaries(conn, trending).await?,
+        new: load_summaries(conn, new).await?,
+    })
+}
+
+// the home screen for an object type
+pub async fn get_discover_feeds(
+    State(state): State<Arc<AppState>>,
+    Path(object_type): Path<models::ObjectType>,
+) -> Result<impl IntoResponse, ApiError> {
+    // held while the feeds load so theyre only loaded once when the cache expires
+    let mut cached = state.feed_cache.feeds[object_type as usize].lock().await;
+    let feeds = match cached.as_ref() {
+        Some((loaded_at, feeds)) if loaded_at.elapsed() < CACHE_TTL => feeds.clone(),
+        _ => {
+            let mut conn = state.pool.get().await?;
+            let feeds = Bytes::from(
+                serde_json::to_vec(&load_feeds(&mut conn, object_type).await?)
+                    .expect("feeds should always serialize"),
+            );
+            *cached = Some((Instant::now(), feeds.clone()));
+            feeds
+        }
+    };
+    Ok(([(header::CONTENT_TYPE, "application/json")], feeds))
+}
+
+#[derive(Serialize)]
+pub struct FeaturedInfo {
+    pub object: Uuid,
+    pub name: String,
+    pub object_type: i16,
+    pub position: i32,
+    pub starts_at: Option<u64>,
+    pub ends_at: Option<u64>,
+    pub added_by: Option<Uuid>,
+}
+
+// every featured object including ones that havent started or have ended
+pub async fn get_featured(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<Vec<FeaturedInfo>>, ApiError> {
+    let mut conn = state.pool.get().await?;
+    require_admin(&mut conn, user_id).await?;
+
+    let featured = featured_objects::table
+        .inner_join(objects::table)
+        .select((
+            FeaturedObject::as_select(),
+            objects::name,
+            objects::object_type,
+        ))
+        .order((
+            featured_objects::position.asc(),
+            featured_objects::object.asc(),
+        ))
+        .load::<(FeaturedObject, String, i16)>(&mut conn)
+        .await?;
+
+    Ok(Json(
+        featured
+            .into_iter()
+            .map(|(featured, name, object_type)| FeaturedInfo {
+                object: featured.object,
+                name,
+                object_type,
+                position: featured.position,
+                starts_at: featured.starts_at.map(to_secs),
+                ends_at: featured.ends_at.map(to_secs),
+                added_by: featured.added_by,
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct FeaturedUpload {
+    position: i32,
+    // seconds since the unix epoch, featured from now until removed if not set
+    starts_at: Option<u64>,
+    ends_at: Option<u64>,
+}
+
+// features an object or changes when and where its featured
+pub async fn set_featured(
+    State(state): State<Arc<AppState>>,
+    Path(object_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<FeaturedUpload>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    require_admin(&mut conn, user_id).await?;
+
+    if let (Some(starts_at), Some(ends_at)) = (json.starts_at, json.ends_at)
+        && ends_at <= starts_at
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some("ends_at must be after starts_at".to_owned()),
+            }),
+        ));
+    }
+
+    if objects::table
+        .count()
+        .filter(objects::id.eq(object_id))
+        .get_result::<i64>(&mut conn)
+        .await?
+        == 0
+    {
+        return Err(ApiError::not_found());
+    }
+
+    let featured = FeaturedObject {
+        object: object_id,
+        position: json.position,
+        starts_at: json.starts_at.map(from_secs),
+        ends_at: json.ends_at.map(from_secs),
+        added_by: Some(user_id),
+        created_at: SystemTime::now(),
+    };
+    insert_into(featured_objects::table)
+        .values(&featured)
+        .on_conflict(featured_objects::object)
+        .do_update()
+        .set((
+            featured_objects::position.eq(featured.position),
+            featured_objects::starts_at.eq(featured.starts_at),
+            featured_objects::ends_at.eq(featured.ends_at),
+            featured_objects::added_by.eq(featured.added_by),
+        ))
+        .execute(&mut conn)
+        .await?;
+
+    state.feed_cache.clear().await;
+    Ok(())
+}
+
+pub async fn remove_featured(
+    State(state): State<Arc<AppState>>,
+    Path(object_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    require_admin(&mut conn, user_id).await?;
+
+    if diesel::delete(featured_objects::table)
+        .filter(featured_objects::object.eq(object_id))
+        .execute(&mut conn)
+        .await?
+        == 0
+    {
+        return Err(ApiError::not_found());
+    }
+
+    state.feed_cache.clear().await;
+    Ok(())
+}
+
+pub fn discover_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(DISCOVER_ROUTE, get(get_discover_feeds))
+        .route(FEATURED_ROUTE, get(get_featured))
+        .route(
+            FEATURED_OBJECT_ROUTE,
+            put(set_featured).delete(remove_featured),
+        )
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,208 @@
+use image::DynamicImage;
+use image::ImageFormat;
+use image::ImageReader;
+use image::Limits;
+use serde::Deserialize;
+use std::io::Cursor;
+use uuid::Uuid;
+
+// uploaded images larger than this in either dimension are rejected
+const MAX_IMAGE_DIMENSION: u32 = 4096;
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageSize {
+    Small,
+    Medium,
+    Large,
+    #[default]
+    Original,
+}
+
+impl ImageSize {
+    pub const THUMBNAILS: [ImageSize; 3] = [ImageSize::Small, ImageSize::Medium, ImageSize::Large];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageSize::Small => "small",
+            ImageSize::Medium => "medium",
+            ImageSize::Large => "large",
+            ImageSize::Original => "original",
+        }
+    }
+
+    // length of the longest side in pixels
+    fn max_dimension(self) -> u32 {
+        match self {
+            ImageSize::Small => 128,
+            ImageSize::Medium => 256,
+            ImageSize::Large => 512,
+            ImageSize::Original => MAX_IMAGE_DIMENSION,
+        }
+    }
+
+    // thumbnails are stored next to the original with the size appended to the key
+    pub fn key(self, object_id: Uuid) -> String {
+        match self {
+            ImageSize::Original => object_id.to_string(),
+            _ => format!("{}-{}", object_id, self.name()),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImageError {
+    #[error("Image must be a png, jpeg or webp.")]
+    UnsupportedFormat,
+    #[error("Image can be at most {0}x{0} pixels.", MAX_IMAGE_DIMENSION)]
+    TooLarge,
+    #[error("Image could not be decoded: {0}")]
+    Invalid(#[from] image::ImageError),
+}
+
+pub struct ProcessedImage {
+    pub content_type: &'static str,
+    pub original: Vec<u8>,
+    pub thumbnails: Vec<(ImageSize, Vec<u8>)>,
+}
+
+// decoding is cpu heavy so this should be called from spawn_blocking
+pub fn process_image(data: &[u8]) -> Result<ProcessedImage, ImageError> {
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|_| ImageError::UnsupportedFormat)?;
+    let format = match reader.format() {
+        Some(format @ (ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::WebP)) => format,
+        _ => return Err(ImageError::UnsupportedFormat),
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_IMAGE_DIMENSION);
+    limits.max_image_height = Some(MAX_IMAGE_DIMENSION);
+    reader.limits(limits);
+
+    let image = reader.decode().map_err(|error| match error {
+        image::ImageError::Limits(_) => ImageError::TooLarge,
+        error => ImageError::Invalid(error),
+    })?;
+
+    // re-encoding drops any metadata (exif, location etc) the upload had
+    let original = encode(&image, format)?;
+
+    let mut thumbnails = Vec::with_capacity(ImageSize::THUMBNAILS.len());
+    for size in ImageSize::THUMBNAILS {
+        let max_dimension = size.max_dimension();
+        // dont upscale images that are already smaller than the thumbnail
+        if image.width() <= max_dimension && image.height() <= max_dimension {
+            thumbnails.push((size, original.clone()));
+        } else {
+            thumbnails.push((
+                size,
+                encode(&image.thumbnail(max_dimension, max_dimension), format)?,
+            ));
+        }
+    }
+
+    Ok(ProcessedImage {
+        content_type: format.to_mime_type(),
+        original,
+        thumbnails,
+    })
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, image::ImageError> {
+    let mut out = Cursor::new(Vec::new());
+    // jpeg has no alpha channel and the webp encoder only takes 8 bit colour
+    match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8()).write_to(&mut out, format)?,
+        _ => DynamicImage::ImageRgba8(image.to_rgba8()).write_to(&mut out, format)?,
+    }
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        encode(&DynamicImage::new_rgba8(width, height), ImageFormat::Png).unwrap()
+    }
+
+    fn dimensions(data: &[u8]) -> (u32, u32) {
+        let image = image::load_from_memory(data).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn makes_thumbnails_of_each_size() {
+        let processed = process_image(&png(1024, 512)).unwrap();
+        assert_eq!(processed.content_type, "image/png");
+        assert_eq!(dimensions(&processed.original), (1024, 512));
+
+        let sizes: Vec<_> = processed
+            .thumbnails
+            .iter()
+            .map(|(size, data)| (size.name(), dimensions(data)))
+            .collect();
+        assert_eq!(
+            sizes,
+            [
+                ("small", (128, 64)),
+                ("medium", (256, 128)),
+                ("large", (512, 256)),
+            ]
+        );
+    }
+
+    #[test]
+    fn doesnt_upscale_small_images() {
+        let processed = process_image(&png(200, 100)).unwrap();
+        for (size, data) in &processed.thumbnails {
+            let expected = match size {
+                ImageSize::Small => (128, 64),
+                _ => (200, 100),
+            };
+            assert_eq!(dimensions(data), expected, "{}", size.name());
+        }
+    }
+
+    #[test]
+    fn keeps_jpeg_as_jpeg() {
+        let jpeg = encode(&DynamicImage::new_rgb8(64, 64), ImageFormat::Jpeg).unwrap();
+        assert_eq!(process_image(&jpeg).unwrap().content_type, "image/jpeg");
+    }
+
+    #[test]
+    fn rejects_oversized_images() {
+        assert!(matches!(
+            process_image(&png(MAX_IMAGE_DIMENSION + 1, 1)),
+            Err(ImageError::TooLarge)
+        ));
+        assert!(matches!(
+            process_image(&png(1, MAX_IMAGE_DIMENSION + 1)),
+            Err(ImageError::TooLarge)
+        ));
+        assert!(process_image(&png(MAX_IMAGE_DIMENSION, 1)).is_ok());
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(matches!(
+            process_image(b"GIF89a\x01\x00\x01\x00\x00\x00\x00;"),
+            Err(ImageError::UnsupportedFormat)
+        ));
+        assert!(matches!(
+            process_image(b"not an image at all"),
+            Err(ImageError::UnsupportedFormat)
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupt_images() {
+        let data = png(64, 64);
+        assert!(matches!(
+            process_image(&data[..data.len() / 2]),
+            Err(ImageError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+use crate::ApiError;
+use crate::models::ObjectKey;
+use crate::schema::object_keys;
+use crate::schema::objects;
+use aes_gcm::Aes256Gcm;
+use aes_gcm::Key;
+use aes_gcm::KeyInit;
+use aes_gcm::Nonce;
+use aes_gcm::aead::Aead;
+use aes_gcm::aead::Payload;
+use diesel::dsl::max;
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use rand_core::TryRngCore;
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+// godot packs are encrypted with aes-256, each file gets a 16 byte iv
+const OBJECT_KEY_SIZE: usize = 32;
+const OBJECT_IV_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+// objects migrated without a legacy key have no key row, their packages arent encrypted
+// and they keep this version until a package encrypted with a rotated key is uploaded
+pub const UNENCRYPTED_VERSION: i32 = 0;
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeyError {
+    #[error("failed to generate random bytes: {0}")]
+    Rng(#[from] rand_core::OsError),
+    #[error("failed to wrap object key")]
+    Wrap,
+    #[error("failed to unwrap object key, the master key may have changed")]
+    Unwrap,
+    #[error("object key {0} version {1} was never wrapped")]
+    NotWrapped(Uuid, i32),
+    #[error("database error: {0}")]
+    Database(#[from] diesel::result::Error),
+}
+
+// object keys are encrypted (wrapped) with this before being stored
+// so a database leak alone isnt enough to decrypt any packages
+pub struct MasterKey(Aes256Gcm);
+
+impl MasterKey {
+    // OBJECT_MASTER_KEY_FILE is a path to a file containing the hex encoded key
+    // OBJECT_MASTER_KEY can be used to pass the hex encoded key directly instead
+    pub fn from_env() -> Self {
+        let hex_key = match env::var("OBJECT_MASTER_KEY_FILE") {
+            Ok(path) => fs::read_to_string(path).expect("failed to read OBJECT_MASTER_KEY_FILE"),
+            Err(_) => env::var("OBJECT_MASTER_KEY")
+                .expect("OBJECT_MASTER_KEY or OBJECT_MASTER_KEY_FILE must be set"),
+        };
+        let key = hex::decode(hex_key.trim()).expect("object master key must be hex encoded");
+        assert!(
+            key.len() == 32,
+            "object master key must be 32 bytes, got {}",
+            key.len()
+        );
+        MasterKey(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)))
+    }
+
+    // the object id and version are authenticated too so a wrapped key cant be copied to another object
+    fn wrap(&self, object_id: Uuid, version: i32, key: &[u8]) -> Result<Vec<u8>, KeyError> {
+        let mut nonce = [0u8; NONCE_SIZE];
+        rand_core::OsRng.try_fill_bytes(&mut nonce)?;
+        let aad = associated_data(object_id, version);
+        let ciphertext = self
+            .0
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: key,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| KeyError::Wrap)?;
+
+        let mut wrapped = nonce.to_vec();
+        wrapped.extend(ciphertext);
+        Ok(wrapped)
+    }
+
+    fn unwrap(&self, object_id: Uuid, version: i32, wrapped: &[u8]) -> Result<Vec<u8>, KeyError> {
+        if wrapped.len() < NONCE_SIZE {
+            return Err(KeyError::Unwrap);
+        }
+        let (nonce, ciphertext) = wrapped.split_at(NONCE_SIZE);
+        let aad = associated_data(object_id, version);
+        self.0
+            .decrypt(
+                Nonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| KeyError::Unwrap)
+    }
+}
+
+fn associated_data(object_id: Uuid, version: i32) -> Vec<u8> {
+    let mut aad = object_id.as_bytes().to_vec();
+    aad.extend(version.to_be_bytes());
+    aad
+}
+
+#[derive(Serialize)]
+pub struct ObjectKeyInfo {
+    pub version: i32,
+    pub key: Vec<u8>,
+    pub iv: Vec<u8>,
+}
+
+// generates a key for the next version of an object
+// it only becomes the current key once a package encrypted with it is uploaded
+pub async fn generate_key(
+    conn: &mut AsyncPgConnection,
+    master_key: &MasterKey,
+    object_id: Uuid,
+) -> Result<ObjectKeyInfo, ApiError> {
+    let mut key = vec![0u8; OBJECT_KEY_SIZE];
+    let mut iv = vec![0u8; OBJECT_IV_SIZE];
+    rand_core::OsRng
+        .try_fill_bytes(&mut key)
+        .map_err(KeyError::from)?;
+    rand_core::OsRng
+        .try_fill_bytes(&mut iv)
+        .map_err(KeyError::from)?;
+
+    let wrap_key = key.clone();
+    let wrap_iv = iv.clone();
+    let version = conn
+        .transaction::<_, ApiError, _>(|conn| {
+            async move {
+                // locks the object so two rotations at once cant pick the same version
+                objects::table
+                    .select(objects::id)
+                    .filter(objects::id.eq(object_id))
+                    .for_update()
+                    .first::<Uuid>(conn)
+                    .await?;
+
+                let version = object_keys::table
+                    .select(max(object_keys::version))
+                    .filter(object_keys::object.eq(object_id))
+                    .first::<Option<i32>>(conn)
+                    .await?
+                    .unwrap_or(0)
+                    + 1;
+
+                insert_into(object_keys::table)
+                    .values(ObjectKey {
+                        object: object_id,
+                        version,
+                        wrapped_key: master_key.wrap(object_id, version, &wrap_key)?,
+                        iv: wrap_iv,
+                        wrapped: true,
+                        created_at: SystemTime::now(),
+                    })
+                    .execute(conn)
+                    .await?;
+                Ok(version)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    Ok(ObjectKeyInfo { version, key, iv })
+}
+
+pub async fn get_key(
+    conn: &mut AsyncPgConnection,
+    master_key: &MasterKey,
+    object_id: Uuid,
+    version: i32,
+) -> Result<Option<ObjectKeyInfo>, ApiError> {
+    let Some(object_key) = object_keys::table
+        .select(ObjectKey::as_select())
+        .filter(object_keys::object.eq(object_id))
+        .filter(object_keys::version.eq(version))
+        .first(conn)
+        .await
+        .optional()?
+    else {
+        return Ok(None);
+    };
+
+    // legacy keys are wrapped at startup so this should never happen
+    if !object_key.wrapped {
+        return Err(KeyError::NotWrapped(object_id, version).into());
+    }
+    let key = master_key.unwrap(object_id, version, &object_key.wrapped_key)?;
+
+    Ok(Some(ObjectKeyInfo {
+        version,
+        key,
+        iv: object_key.iv,
+    }))
+}
+
+// keys chosen by clients before the server managed them were migrated in as is,
+// theyre wrapped before the server starts so no plaintext keys stay in the database
+pub async fn wrap_legacy_keys(
+    conn: &mut AsyncPgConnection,
+    master_key: &MasterKey,
+) -> Result<usize, KeyError> {
+    conn.transaction::<_, KeyError, _>(|conn| {
+        async move {
+            let legacy = object_keys::table
+                .select(ObjectKey::as_select())
+                .filter(object_keys::wrapped.eq(false))
+                .for_update()
+                .load::<ObjectKey>(conn)
+                .await?;
+
+            for object_key in &legacy {
+                diesel::update(object_keys::table)
+                    .filter(object_keys::object.eq(object_key.object))
+                    .filter(object_keys::version.eq(object_key.version))
+                    .set((
+                        object_keys::wrapped_key.eq(master_key.wrap(
+                            object_key.object,
+                            object_key.version,
+                            &object_key.wrapped_key,
+                        )?),
+                        object_keys::wrapped.eq(true),
+                    ))
+                    .execute(conn)
+                    .await?;
+            }
+            Ok(legacy.len())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn master_key() -> MasterKey {
+        MasterKey(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&[7u8; 32])))
+    }
+
+    #[test]
+    fn wrap_round_trip() {
+        let master_key = master_key();
+        let object_id = Uuid::new_v4();
+        let key = [42u8; OBJECT_KEY_SIZE];
+
+        let wrapped = master_key.wrap(object_id, 3, &key).unwrap();
+        assert_ne!(&wrapped[NONCE_SIZE..], &key[..]);
+        assert_eq!(master_key.unwrap(object_id, 3, &wrapped).unwrap(), key);
+    }
+
+    #[test]
+    fn wrapping_twice_uses_a_new_nonce() {
+        let master_key = master_key();
+        let object_id = Uuid::new_v4();
+        let key = [42u8; OBJECT_KEY_SIZE];
+
+        assert_ne!(
+            master_key.wrap(object_id, 1, &key).unwrap(),
+            master_key.wrap(object_id, 1, &key).unwrap()
+        );
+    }
+
+    #[test]
+    fn unwrap_rejects_other_object_or_version() {
+        let master_key = master_key();
+        let object_id = Uuid::new_v4();
+        let wrapped = master_key
+            .wrap(object_id, 1, &[42u8; OBJECT_KEY_SIZE])
+            .unwrap();
+
+        assert!(matches!(
+            master_key.unwrap(Uuid::new_v4(), 1, &wrapped),
+            Err(KeyError::Unwrap)
+        ));
+        assert!(matches!(
+            master_key.unwrap(object_id, 2, &wrapped),
+            Err(KeyError::Unwrap)
+        ));
+    }
+
+    #[test]
+    fn unwrap_rejects_other_master_key_and_bad_input() {
+        let object_id = Uuid::new_v4();
+        let wrapped = master_key()
+            .wrap(object_id, 1, &[42u8; OBJECT_KEY_SIZE])
+            .unwrap();
+        let other = MasterKey(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&[8u8; 32])));
+
+        assert!(matches!(
+            other.unwrap(object_id, 1, &wrapped),
+            Err(KeyError::Unwrap)
+        ));
+        assert!(matches!(
+            master_key().unwrap(object_id, 1, &wrapped[..NONCE_SIZE - 1]),
+            Err(KeyError::Unwrap)
+        ));
+
+        let mut tampered = wrapped.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            master_key().unwrap(object_id, 1, &tampered),
+            Err(KeyError::Unwrap)
+        ));
+    }
+}
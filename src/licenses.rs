@@ -0,0 +1,179 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::ErrorCode;
+use crate::ErrorInfo;
+use crate::auth;
+use crate::models::License;
+use crate::schema::licenses;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::{Json, Router, routing::get};
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+
+const LICENSES_ROUTE: &str = "/licenses";
+const LICENSE_ID_ROUTE: &str = constcat::concat!(LICENSES_ROUTE, "/{id}");
+
+// everything but the text, which can be up to 100000 chars for custom licenses
+#[derive(Serialize, Queryable, Selectable)]
+#[diesel(table_name = licenses)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct LicenseInfo {
+    #[serde(rename = "id")]
+    pub license: i32,
+    pub spdx_id: Option<String>,
+    pub name: String,
+    pub url: Option<String>,
+    pub custom: bool,
+    pub allows_remix: bool,
+    pub allows_commercial: bool,
+    pub requires_attribution: bool,
+    pub share_alike: bool,
+}
+
+// either an spdx id from the catalogue, e.g. "CC-BY-4.0", or a custom license
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum LicenseUpload {
+    Standard(String),
+    Custom(CustomLicense),
+}
+
+#[derive(Deserialize)]
+pub struct CustomLicense {
+    name: String,
+    text: String,
+    allows_remix: bool,
+    allows_commercial: bool,
+    requires_attribution: bool,
+    share_alike: bool,
+}
+
+// finds the license an upload refers to, custom licenses are only stored once
+pub async fn license_for_upload(
+    conn: &mut AsyncPgConnection,
+    license: &LicenseUpload,
+) -> Result<i32, ApiError> {
+    match license {
+        LicenseUpload::Standard(spdx_id) => licenses::table
+            .select(licenses::license)
+            .filter(licenses::spdx_id.eq(spdx_id))
+            .filter(licenses::custom.eq(false))
+            .first::<i32>(conn)
+            .await
+            .optional()?
+            .ok_or(ApiError::WithResponse(
+                StatusCode::BAD_REQUEST,
+                Json(ErrorInfo {
+                    error_code: ErrorCode::InvalidRequest,
+                    error_message: Some(format!(
+                        "Unknown license {:?}, use a custom license instead.",
+                        spdx_id
+                    )),
+                }),
+            )),
+        LicenseUpload::Custom(custom) => {
+            if custom.name.is_empty()
+                || custom.name.len() > 128
+                || custom.text.is_empty()
+                || custom.text.len() > 100000
+            {
+                return Err(ApiError::WithResponse(
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorInfo {
+                        error_code: ErrorCode::BadRequestLength,
+                        error_message: Some(String::from(
+                            "License name or text was wrong length. This shouldnt happen",
+                        )),
+                    }),
+                ));
+            }
+
+            if let Some(license_number) = licenses::table
+                .select(licenses::license)
+                .filter(licenses::custom.eq(true))
+                .filter(licenses::name.eq(&custom.name))
+                .filter(licenses::text.eq(&custom.text))
+                .filter(licenses::allows_remix.eq(custom.allows_remix))
+                .filter(licenses::allows_commercial.eq(custom.allows_commercial))
+                .filter(licenses::requires_attribution.eq(custom.requires_attribution))
+                .filter(licenses::share_alike.eq(custom.share_alike))
+                .first::<i32>(conn)
+                .await
+                .optional()?
+            {
+                return Ok(license_number);
+            }
+
+            Ok(insert_into(licenses::table)
+                .values((
+                    licenses::text.eq(&custom.text),
+                    licenses::name.eq(&custom.name),
+                    licenses::custom.eq(true),
+                    licenses::allows_remix.eq(custom.allows_remix),
+                    licenses::allows_commercial.eq(custom.allows_commercial),
+                    licenses::requires_attribution.eq(custom.requires_attribution),
+                    licenses::share_alike.eq(custom.share_alike),
+                ))
+                .returning(licenses::license)
+                .get_result(conn)
+                .await?)
+        }
+    }
+}
+
+// the standard licenses objects can be uploaded with
+pub async fn get_licenses(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<LicenseInfo>>, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    Ok(Json(
+        licenses::table
+            .select(LicenseInfo::as_select())
+            .filter(licenses::custom.eq(false))
+            .order(licenses::license.asc())
+            .load(&mut conn)
+            .await?,
+    ))
+}
+
+pub async fn get_license(
+    State(state): State<Arc<AppState>>,
+    Path(license_id): Path<i32>,
+) -> Result<Json<License>, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    licenses::table
+        .select(License::as_select())
+        .filter(licenses::license.eq(license_id))
+        .first(&mut conn)
+        .await
+        .optional()?
+        .map(Json)
+        .ok_or(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ))
+}
+
+pub fn licenses_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(LICENSES_ROUTE, get(get_licenses))
+        .route(LICENSE_ID_ROUTE, get(get_license))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
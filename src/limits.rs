@@ -0,0 +1,103 @@
+use crate::models::ObjectType;
+use std::env;
+
+pub const MB: i64 = 1024 * 1024;
+pub const GB: i64 = 1024 * MB;
+
+// default limits in bytes, indexed by user trust level
+// users with a trust level past the end of the list get the last entry
+// object size defaults are in object_types.rs
+const DEFAULT_IMAGE_SIZES: [i64; 3] = [2 * MB, 8 * MB, 16 * MB];
+const DEFAULT_STORAGE_QUOTAS: [i64; 3] = [GB, 10 * GB, 100 * GB];
+
+pub struct UploadLimits {
+    // indexed by ObjectType
+    object_sizes: Vec<Vec<i64>>,
+    image_sizes: Vec<i64>,
+    storage_quotas: Vec<i64>,
+}
+
+impl UploadLimits {
+    // each limit can be overridden with a comma separated list of sizes in bytes
+    // e.g. MAX_WORLD_SIZES=268435456,1073741824
+    pub fn from_env() -> Self {
+        Self {
+            object_sizes: ObjectType::ALL
+                .into_iter()
+                .map(|x| limits_from_env(x.info().max_sizes_env, &x.info().default_max_sizes))
+                .collect(),
+            image_sizes: limits_from_env("MAX_IMAGE_SIZES", &DEFAULT_IMAGE_SIZES),
+            storage_quotas: limits_from_env("STORAGE_QUOTAS", &DEFAULT_STORAGE_QUOTAS),
+        }
+    }
+
+    pub fn max_object_size(&self, object_type: ObjectType, trust: i32) -> i64 {
+        for_trust(&self.object_sizes[object_type as usize], trust)
+    }
+
+    pub fn max_image_size(&self, trust: i32) -> i64 {
+        for_trust(&self.image_sizes, trust)
+    }
+
+    pub fn storage_quota(&self, trust: i32) -> i64 {
+        for_trust(&self.storage_quotas, trust)
+    }
+}
+
+fn for_trust(limits: &[i64], trust: i32) -> i64 {
+    limits[(trust.max(0) as usize).min(limits.len() - 1)]
+}
+
+fn limits_from_env(var: &str, default: &[i64]) -> Vec<i64> {
+    let Ok(value) = env::var(var) else {
+        return default.to_vec();
+    };
+    value
+        .split(',')
+        .map(|x| {
+            x.trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("{} must be a comma separated list of sizes", var))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_limit_by_trust() {
+        let limits = [10, 20, 30];
+        assert_eq!(for_trust(&limits, 0), 10);
+        assert_eq!(for_trust(&limits, 1), 20);
+        assert_eq!(for_trust(&limits, 2), 30);
+    }
+
+    #[test]
+    fn clamps_trust_to_the_list() {
+        let limits = [10, 20, 30];
+        assert_eq!(for_trust(&limits, -1), 10);
+        assert_eq!(for_trust(&limits, i32::MIN), 10);
+        assert_eq!(for_trust(&limits, 3), 30);
+        assert_eq!(for_trust(&limits, i32::MAX), 30);
+        assert_eq!(for_trust(&[5], 2), 5);
+    }
+
+    #[test]
+    fn object_sizes_are_per_type() {
+        let limits = UploadLimits {
+            object_sizes: ObjectType::ALL
+                .into_iter()
+                .map(|x| x.info().default_max_sizes.to_vec())
+                .collect(),
+            image_sizes: DEFAULT_IMAGE_SIZES.to_vec(),
+            storage_quotas: DEFAULT_STORAGE_QUOTAS.to_vec(),
+        };
+        assert_eq!(limits.max_object_size(ObjectType::World, 0), 256 * MB);
+        assert_eq!(limits.max_object_size(ObjectType::Shader, 0), 8 * MB);
+        assert_eq!(limits.max_object_size(ObjectType::AudioPack, 9), GB);
+        assert_eq!(limits.max_image_size(1), 8 * MB);
+        assert_eq!(limits.storage_quota(-5), GB);
+    }
+}
@@ -13,8 +13,20 @@ use std::{env, sync::Arc};
 use tokio::sync::Mutex;
 use tower_http::trace::TraceLayer;
 mod auth;
+mod collaborators;
+mod collections;
+mod creators;
+mod discover;
 mod email;
 mod hash;
+mod images;
+mod keys;
+mod licenses;
+mod limits;
+mod moderation;
+mod object_types;
+mod reviews;
+mod scanning;
 //mod instances;
 mod search;
 // will finish later
@@ -22,7 +34,9 @@ mod search;
 pub mod models;
 mod objects;
 pub mod schema;
+mod tags;
 mod tokens;
+mod usage;
 // will finish later
 //mod user_websocket;
 mod users;
@@ -46,6 +60,10 @@ enum ErrorCode {
     InsufficientPermissions,
     BadRequestLength,
     InvalidRequest,
+    TooLarge,
+    ChecksumMismatch,
+    InvalidImage,
+    PreconditionFailed,
 }
 
 enum ApiError {
@@ -60,6 +78,29 @@ impl<T: Error> From<T> for ApiError {
     }
 }
 
+impl ApiError {
+    // for anything that dosent exist or that the user isnt allowed to see
+    fn not_found() -> Self {
+        Self::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        )
+    }
+
+    fn forbidden(message: &str) -> Self {
+        Self::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some(message.to_owned()),
+            }),
+        )
+    }
+}
+
 impl IntoResponse for ApiError {
     fn into_response(self) -> axum::response::Response {
         match self {
@@ -81,7 +122,13 @@ struct AppState {
     //readonly_pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>,
     pool: Pool<AsyncDieselConnectionManager<AsyncPgConnection>>,
     s3_client: aws_sdk_s3::Client,
+    upload_limits: limits::UploadLimits,
+    // hide unverified objects from everyone but their creator
+    hide_unverified: bool,
+    package_scanners: Vec<scanning::Scanner>,
+    master_key: keys::MasterKey,
     hasher_memory: [Mutex<Vec<argon2::Block>>; HASHER_MEMORY_BLOCKS],
+    feed_cache: discover::FeedCache,
 }
 
 #[tokio::main]
@@ -101,17 +148,56 @@ async fn main() {
             .await
             .expect("failed to connect to the database"),
         s3_client: aws_sdk_s3::Client::new(&aws_config::load_from_env().await),
+        upload_limits: limits::UploadLimits::from_env(),
+        hide_unverified: env::var("HIDE_UNVERIFIED").is_ok_and(|x| x == "true"),
+        package_scanners: scanning::scanners_from_env(),
+        master_key: keys::MasterKey::from_env(),
         hasher_memory: std::array::from_fn(|_| {
             Mutex::new(vec![argon2::Block::new(); HASHER_MEMORY as usize])
         }),
+        feed_cache: discover::FeedCache::new(),
     });
 
+    let wrapped = keys::wrap_legacy_keys(
+        &mut app_state
+            .pool
+            .get()
+            .await
+            .expect("failed to get a connection"),
+        &app_state.master_key,
+    )
+    .await
+    .expect("failed to wrap legacy object keys");
+    if wrapped > 0 {
+        tracing::info!("wrapped {} legacy object keys", wrapped);
+    }
+
+    tokio::spawn(usage::usage_rollup_task(app_state.clone()));
+
     let app = Router::new()
         .route(ROUTE_ORIGIN, get(|| async { http::StatusCode::OK }))
         .nest(ROUTE_ORIGIN, users::users_router(app_state.clone()))
         .nest(ROUTE_ORIGIN, tokens::tokens_router(app_state.clone()))
         .nest(ROUTE_ORIGIN, objects::objects_router(app_state.clone()))
+        .nest(
+            ROUTE_ORIGIN,
+            collaborators::collaborators_router(app_state.clone()),
+        )
+        .nest(
+            ROUTE_ORIGIN,
+            collections::collections_router(app_state.clone()),
+        )
+        .nest(ROUTE_ORIGIN, creators::creators_router(app_state.clone()))
+        .nest(ROUTE_ORIGIN, discover::discover_router(app_state.clone()))
+        .nest(ROUTE_ORIGIN, reviews::reviews_router(app_state.clone()))
+        .nest(ROUTE_ORIGIN, usage::usage_router(app_state.clone()))
+        .nest(ROUTE_ORIGIN, licenses::licenses_router(app_state.clone()))
         .nest(ROUTE_ORIGIN, search::search_router(app_state.clone()))
+        .nest(ROUTE_ORIGIN, tags::tags_router(app_state.clone()))
+        .nest(
+            ROUTE_ORIGIN,
+            moderation::moderation_router(app_state.clone()),
+        )
         .layer(TraceLayer::new_for_http());
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:80").await.unwrap();
@@ -6,22 +6,115 @@ use uuid::Uuid;
 use crate::schema::*;
 
 // diesel dosent like enums so we dont define these on db
-#[derive(Deserialize, Clone, Copy)]
+// see object_types.rs for everything that depends on the type
+// paths use the name or alias from there, e.g. /audio_pack/{uuid} or /AudioPack/{uuid}
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(try_from = "String")]
 pub enum ObjectType {
     World = 0,
     Avatar = 1,
+    Prop = 2,
+    // shaders and materials
+    Shader = 3,
+    AudioPack = 4,
+}
+
+// stored on objects as a bitset, each flag is the index of its bit
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ObjectFlag {
+    // not safe for work
+    Nsfw = 0,
+    // has scripts that run on the client
+    ContainsScripts = 1,
+    // other users are allowed to make a copy of it
+    AllowCloning = 2,
+    // runs on standalone headsets like the quest
+    QuestCompatible = 3,
+}
+
+impl ObjectFlag {
+    pub const ALL: [ObjectFlag; 4] = [
+        ObjectFlag::Nsfw,
+        ObjectFlag::ContainsScripts,
+        ObjectFlag::AllowCloning,
+        ObjectFlag::QuestCompatible,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nsfw" => Some(ObjectFlag::Nsfw),
+            "contains_scripts" => Some(ObjectFlag::ContainsScripts),
+            "allow_cloning" => Some(ObjectFlag::AllowCloning),
+            "quest_compatible" => Some(ObjectFlag::QuestCompatible),
+            _ => None,
+        }
+    }
+
+    pub fn bit(self) -> i32 {
+        1 << self as i32
+    }
+
+    pub fn to_bits(flags: &[ObjectFlag]) -> i32 {
+        flags.iter().fold(0, |bits, flag| bits | flag.bit())
+    }
+
+    pub fn from_bits(bits: i32) -> Vec<ObjectFlag> {
+        ObjectFlag::ALL
+            .into_iter()
+            .filter(|flag| bits & flag.bit() != 0)
+            .collect()
+    }
+}
+
+// private objects are only listed to their creator and collaborators
+#[derive(Clone, Copy, PartialEq)]
+pub enum Publicity {
+    Public = 0,
+    Private = 1,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScanStatus {
+    Pending = 0,
+    Clean = 1,
+    Quarantined = 2,
+    // the scanner itself failed, the package needs to be uploaded again
+    Failed = 3,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum UseKind {
+    Download = 0,
+    // reported by the client when it joins a world or loads an object
+    Join = 1,
+}
+
+// roles are ordered, each one can do everything the ones before it can
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum CollaboratorRole {
+    // can see the object even when it would otherwise be hidden
+    Viewer = 0,
+    // can upload new packages and images
+    Uploader = 1,
+    // can also change the objects details
+    Editor = 2,
+}
+
+impl CollaboratorRole {
+    pub fn from_i16(role: i16) -> Option<Self> {
+        match role {
+            0 => Some(CollaboratorRole::Viewer),
+            1 => Some(CollaboratorRole::Uploader),
+            2 => Some(CollaboratorRole::Editor),
+            _ => None,
+        }
+    }
 }
 
 #[derive(
-    Queryable,
-    Identifiable,
-    Associations,
-    Serialize,
-    Selectable,
-    Insertable,
-    Debug,
-    Clone,
-    AsChangeset,
+    Queryable, Identifiable, Associations, Selectable, Insertable, Debug, Clone, AsChangeset,
 )]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 #[diesel(belongs_to(User, foreign_key = creator))]
@@ -30,18 +123,39 @@ pub struct Object {
     pub id: Uuid,
     pub name: String,
     pub description: String,
-    pub flags: Vec<Option<bool>>,
+    // bitset of ObjectFlag
+    pub flags: i32,
     pub updated_at: SystemTime,
     pub created_at: SystemTime,
     pub verified: bool,
     pub object_size: i64,
+    // the original image and all its thumbnails
     pub image_size: i64,
     pub creator: Uuid,
     pub object_type: i16,
     pub publicity: i16,
     pub license: i32,
-    pub encryption_key: Vec<u8>,
-    pub encryption_iv: Vec<u8>,
+    pub object_sha256: Vec<u8>,
+    // of the re-encoded original image, not the uploaded one
+    pub image_sha256: Vec<u8>,
+    pub rejection_reason: Option<String>,
+    pub reviewed_by: Option<Uuid>,
+    pub reviewed_at: Option<SystemTime>,
+    pub scan_status: i16,
+    // the object_keys version packages are currently encrypted with, 0 if there isnt one
+    pub key_version: i32,
+    pub favourite_count: i32,
+    pub rating_count: i32,
+    pub rating_sum: i32,
+    pub weekly_uses: i32,
+}
+
+// indices into User::permisions
+#[derive(Clone, Copy)]
+pub enum Permission {
+    Verifier = 0,
+    // manages the featured objects in the discover feeds
+    Admin = 1,
 }
 
 #[derive(Queryable, Selectable, Insertable, Serialize)]
@@ -105,11 +219,23 @@ pub struct Token {
     pub renewable: bool,
 }
 
-#[derive(Queryable, Selectable, Insertable)]
+// standard licenses come from the catalogue and have an spdx id,
+// custom licenses are uploaded with an object and have their full text
+#[derive(Queryable, Selectable, Insertable, Serialize)]
 #[diesel(check_for_backend(diesel::pg::Pg))]
 pub struct License {
-    license: i32,
-    text: String,
+    #[serde(rename = "id")]
+    pub license: i32,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub text: String,
+    pub spdx_id: Option<String>,
+    pub name: String,
+    pub url: Option<String>,
+    pub custom: bool,
+    pub allows_remix: bool,
+    pub allows_commercial: bool,
+    pub requires_attribution: bool,
+    pub share_alike: bool,
 }
 
 #[derive(Queryable, Selectable, Associations, Insertable)]
@@ -119,3 +245,180 @@ pub struct Tag {
     object: Uuid,
     tag: String,
 }
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct ObjectScan {
+    pub object: Uuid,
+    pub object_sha256: Vec<u8>,
+    pub scanned_at: SystemTime,
+    pub status: i16,
+    pub details: Option<String>,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct ObjectCollaborator {
+    pub object: Uuid,
+    pub user: Uuid,
+    pub role: i16,
+    pub added_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct CollaboratorInvite {
+    pub object: Uuid,
+    pub user: Uuid,
+    pub role: i16,
+    pub invited_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct OwnershipTransfer {
+    pub object: Uuid,
+    pub from_user: Uuid,
+    pub to_user: Uuid,
+    pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct ObjectKey {
+    pub object: Uuid,
+    pub version: i32,
+    pub wrapped_key: Vec<u8>,
+    pub iv: Vec<u8>,
+    pub wrapped: bool,
+    pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct Favourite {
+    pub user: Uuid,
+    pub object: Uuid,
+    pub added_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Insertable, Identifiable, AsChangeset)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Collection {
+    pub id: Uuid,
+    pub owner: Uuid,
+    pub name: String,
+    pub description: String,
+    pub public: bool,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Collection, foreign_key = collection))]
+pub struct CollectionItem {
+    pub collection: Uuid,
+    pub object: Uuid,
+    pub position: i32,
+    pub added_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct Review {
+    pub object: Uuid,
+    pub user: Uuid,
+    pub rating: i16,
+    pub text: String,
+    pub reply: Option<String>,
+    pub replied_at: Option<SystemTime>,
+    pub created_at: SystemTime,
+    pub updated_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct ReviewReport {
+    pub object: Uuid,
+    pub review_user: Uuid,
+    pub reporter: Uuid,
+    pub reason: String,
+    pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+pub struct ObjectUse {
+    pub object: Uuid,
+    pub user: Uuid,
+    pub day: i32,
+    pub kind: i16,
+    pub created_at: SystemTime,
+}
+
+#[derive(Queryable, Selectable, Associations, Insertable, AsChangeset)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+#[diesel(belongs_to(Object, foreign_key = object))]
+#[diesel(treat_none_as_null = true)]
+pub struct FeaturedObject {
+    pub object: Uuid,
+    pub position: i32,
+    pub starts_at: Option<SystemTime>,
+    pub ends_at: Option<SystemTime>,
+    pub added_by: Option<Uuid>,
+    pub created_at: SystemTime,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_bits_match_their_index() {
+        assert_eq!(ObjectFlag::Nsfw.bit(), 1);
+        assert_eq!(ObjectFlag::ContainsScripts.bit(), 2);
+        assert_eq!(ObjectFlag::AllowCloning.bit(), 4);
+        assert_eq!(ObjectFlag::QuestCompatible.bit(), 8);
+    }
+
+    #[test]
+    fn flags_round_trip_through_bits() {
+        // every combination of flags
+        for bits in 0..1 << ObjectFlag::ALL.len() {
+            let flags = ObjectFlag::from_bits(bits);
+            assert_eq!(ObjectFlag::to_bits(&flags), bits);
+        }
+        assert_eq!(
+            ObjectFlag::from_bits(0b1001),
+            [ObjectFlag::Nsfw, ObjectFlag::QuestCompatible]
+        );
+    }
+
+    #[test]
+    fn unknown_bits_and_duplicates_are_ignored() {
+        assert_eq!(ObjectFlag::from_bits(!0), ObjectFlag::ALL);
+        assert_eq!(
+            ObjectFlag::to_bits(&[ObjectFlag::Nsfw, ObjectFlag::Nsfw]),
+            ObjectFlag::Nsfw.bit()
+        );
+        assert_eq!(ObjectFlag::to_bits(&[]), 0);
+    }
+
+    #[test]
+    fn flag_names_match_serde() {
+        for flag in ObjectFlag::ALL {
+            let name = serde_json::to_value(flag).unwrap();
+            assert_eq!(ObjectFlag::from_name(name.as_str().unwrap()), Some(flag));
+        }
+        assert_eq!(ObjectFlag::from_name("Nsfw"), None);
+    }
+}
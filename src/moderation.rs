@@ -0,0 +1,396 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::ErrorCode;
+use crate::ErrorInfo;
+use crate::auth;
+use crate::auth::has_permission;
+use crate::models;
+use crate::models::Permission;
+use crate::models::ScanStatus;
+use crate::objects::to_secs;
+use crate::reviews::delete_review;
+use crate::schema::objects;
+use crate::schema::review_reports;
+use crate::schema::reviews;
+use crate::search::Cursor;
+use crate::search::Page;
+use axum::Extension;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::{Json, Router, routing::get, routing::post};
+use diesel::dsl::min;
+use diesel::prelude::*;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+const MODERATION_ROUTE: &str = "/moderation";
+const MODERATION_QUEUE_ROUTE: &str = constcat::concat!(MODERATION_ROUTE, "/queue");
+const MODERATION_OBJECT_ROUTE: &str = constcat::concat!(MODERATION_ROUTE, "/{object_type}/{uuid}");
+const MODERATION_APPROVE_ROUTE: &str = constcat::concat!(MODERATION_OBJECT_ROUTE, "/approve");
+const MODERATION_REJECT_ROUTE: &str = constcat::concat!(MODERATION_OBJECT_ROUTE, "/reject");
+const MODERATION_REVIEWS_ROUTE: &str = constcat::concat!(MODERATION_ROUTE, "/reviews");
+const MODERATION_REVIEW_ROUTE: &str =
+    constcat::concat!(MODERATION_REVIEWS_ROUTE, "/{uuid}/{usr_id}");
+const MODERATION_REVIEW_REMOVE_ROUTE: &str = constcat::concat!(MODERATION_REVIEW_ROUTE, "/remove");
+const MODERATION_REVIEW_DISMISS_ROUTE: &str =
+    constcat::concat!(MODERATION_REVIEW_ROUTE, "/dismiss");
+
+const QUEUE_LENGTH: i64 = 100;
+// what reported review cursors are tagged with so they cant be used for other listings
+const REPORTED_REVIEWS_ORDER: &str = "reported_reviews";
+
+#[derive(Serialize)]
+pub struct QueuedObject {
+    pub id: Uuid,
+    pub name: String,
+    pub object_type: i16,
+    pub creator: Uuid,
+    pub object_size: i64,
+    pub updated_at: u64,
+}
+
+async fn require_verifier(conn: &mut AsyncPgConnection, user_id: Uuid) -> Result<(), ApiError> {
+    if has_permission(conn, user_id, Permission::Verifier).await? {
+        return Ok(());
+    }
+    Err(ApiError::WithResponse(
+        StatusCode::FORBIDDEN,
+        Json(ErrorInfo {
+            error_code: ErrorCode::InsufficientPermissions,
+            error_message: Some("You do not have permission to review objects.".to_owned()),
+        }),
+    ))
+}
+
+// objects waiting for review, oldest first
+// packages only enter the queue once they pass scanning
+// and rejected objects leave it until a new file is uploaded
+pub async fn get_queue(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<Vec<QueuedObject>>, ApiError> {
+    let mut conn = state.pool.get().await?;
+    require_verifier(&mut conn, user_id).await?;
+
+    let queue = objects::table
+        .select((
+            objects::id,
+            objects::name,
+            objects::object_type,
+            objects::creator,
+            objects::object_size,
+            objects::updated_at,
+        ))
+        .filter(objects::verified.eq(false))
+        .filter(objects::rejection_reason.is_null())
+        .filter(objects::object_size.gt(0))
+        .filter(objects::scan_status.eq(ScanStatus::Clean as i16))
+        .order(objects::updated_at.asc())
+        .limit(QUEUE_LENGTH)
+        .load::<(Uuid, String, i16, Uuid, i64, SystemTime)>(&mut conn)
+        .await?;
+
+    Ok(Json(
+        queue
+            .into_iter()
+            .map(
+                |(id, name, object_type, creator, object_size, updated_at)| QueuedObject {
+                    id,
+                    name,
+                    object_type,
+                    creator,
+                    object_size,
+                    updated_at: to_secs(updated_at),
+                },
+            )
+            .collect(),
+    ))
+}
+
+// sets the review outcome, verified objects never have a rejection reason
+// only packages that scanned clean can be approved, anything can be rejected
+async fn review_object(
+    state: Arc<AppState>,
+    object_type: models::ObjectType,
+    object_id: Uuid,
+    user_id: Uuid,
+    rejection_reason: Option<String>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    require_verifier(&mut conn, user_id).await?;
+
+    let Some((creator, scan_status)) = objects::table
+        .select((objects::creator, objects::scan_status))
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .first::<(Uuid, i16)>(&mut conn)
+        .await
+        .optional()?
+    else {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
+    };
+
+    if creator == user_id {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some("You cannot review your own objects.".to_owned()),
+            }),
+        ));
+    }
+
+    if rejection_reason.is_none() && scan_status != ScanStatus::Clean as i16 {
+        return Err(ApiError::WithResponse(
+            StatusCode::CONFLICT,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some(
+                    "Only packages that have been scanned clean can be approved.".to_owned(),
+                ),
+            }),
+        ));
+    }
+
+    diesel::update(objects::table)
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .set((
+            objects::verified.eq(rejection_reason.is_none()),
+            objects::rejection_reason.eq(rejection_reason),
+            objects::reviewed_by.eq(Some(user_id)),
+            objects::reviewed_at.eq(Some(SystemTime::now())),
+        ))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn approve_object(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    review_object(state, object_type, object_id, user_id, None).await
+}
+
+#[derive(Deserialize)]
+pub struct RejectRequest {
+    reason: String,
+}
+
+pub async fn reject_object(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<RejectRequest>,
+) -> Result<(), ApiError> {
+    if json.reason.is_empty() || json.reason.len() > 1024 {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::BadRequestLength,
+                error_message: Some(String::from(
+                    "Rejection reason was wrong length. This shouldnt happen",
+                )),
+            }),
+        ));
+    }
+
+    review_object(state, object_type, object_id, user_id, Some(json.reason)).await
+}
+
+#[derive(Serialize)]
+pub struct ReportedReview {
+    pub object: Uuid,
+    pub user: Uuid,
+    pub rating: i16,
+    pub text: String,
+    pub reasons: Vec<String>,
+    pub first_reported_at: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReportedReviewsQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReportedReviews {
+    pub reviews: Vec<ReportedReview>,
+    // pass back as the cursor parameter to get the next page, missing on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+// reviews with open reports, the longest waiting first
+pub async fn get_reported_reviews(
+    State(state): State<Arc<AppState>>,
+    Extension(user_id): Extension<Uuid>,
+    Query(query): Query<ReportedReviewsQuery>,
+) -> Result<Json<ReportedReviews>, ApiError> {
+    let page = Page::new(query.limit, query.cursor.as_deref(), 1)?;
+
+    let mut conn = state.pool.get().await?;
+    require_verifier(&mut conn, user_id).await?;
+
+    let first_reported_at = min(review_reports::created_at).assume_not_null();
+    let mut reviews_query = reviews::table
+        .inner_join(
+            review_reports::table.on(review_reports::object
+                .eq(reviews::object)
+                .and(review_reports::review_user.eq(reviews::user))),
+        )
+        .group_by((
+            reviews::object,
+            reviews::user,
+            reviews::rating,
+            reviews::text,
+        ))
+        .select((
+            reviews::object,
+            reviews::user,
+            reviews::rating,
+            reviews::text,
+            first_reported_at,
+        ))
+        .order((
+            first_reported_at.asc(),
+            reviews::object.asc(),
+            reviews::user.asc(),
+        ))
+        .limit(page.limit + 1)
+        .into_boxed();
+    if let Some(cursor) = &page.cursor {
+        let (reported_at, usr_id): (SystemTime, Uuid) = cursor.key_for(REPORTED_REVIEWS_ORDER)?;
+        reviews_query = reviews_query.having(
+            first_reported_at
+                .gt(reported_at)
+                .or(first_reported_at.eq(reported_at).and(
+                    reviews::object
+                        .gt(cursor.id)
+                        .or(reviews::object.eq(cursor.id).and(reviews::user.gt(usr_id))),
+                )),
+        );
+    }
+    let mut reported = reviews_query
+        .load::<(Uuid, Uuid, i16, String, SystemTime)>(&mut conn)
+        .await?;
+
+    // one extra row is loaded to tell if theres another page
+    let next_cursor = if reported.len() as i64 > page.limit {
+        reported.truncate(page.limit as usize);
+        reported.last().map(|(object, user, _, _, reported_at)| {
+            Cursor::encode(REPORTED_REVIEWS_ORDER, *object, &(*reported_at, *user))
+        })
+    } else {
+        None
+    };
+
+    // the reasons for the whole page in one go, this can load a few extra
+    // for reviews that share an object or user with one on the page
+    let objects: Vec<Uuid> = reported.iter().map(|x| x.0).collect();
+    let users: Vec<Uuid> = reported.iter().map(|x| x.1).collect();
+    let reasons = review_reports::table
+        .select((
+            review_reports::object,
+            review_reports::review_user,
+            review_reports::reason,
+        ))
+        .filter(review_reports::object.eq_any(&objects))
+        .filter(review_reports::review_user.eq_any(&users))
+        .order(review_reports::created_at.asc())
+        .load::<(Uuid, Uuid, String)>(&mut conn)
+        .await?;
+
+    Ok(Json(ReportedReviews {
+        reviews: reported
+            .into_iter()
+            .map(|(object, user, rating, text, reported_at)| ReportedReview {
+                object,
+                user,
+                rating,
+                text,
+                reasons: reasons
+                    .iter()
+                    .filter(|x| x.0 == object && x.1 == user)
+                    .map(|x| x.2.clone())
+                    .collect(),
+                first_reported_at: to_secs(reported_at),
+            })
+            .collect(),
+        next_cursor,
+    }))
+}
+
+// reports go with the review
+pub async fn remove_reported_review(
+    State(state): State<Arc<AppState>>,
+    Path((object_id, usr_id)): Path<(Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    require_verifier(&mut conn, user_id).await?;
+
+    if !delete_review(&mut conn, object_id, usr_id).await? {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn dismiss_review_reports(
+    State(state): State<Arc<AppState>>,
+    Path((object_id, usr_id)): Path<(Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    require_verifier(&mut conn, user_id).await?;
+
+    diesel::delete(review_reports::table)
+        .filter(review_reports::object.eq(object_id))
+        .filter(review_reports::review_user.eq(usr_id))
+        .execute(&mut conn)
+        .await?;
+    Ok(())
+}
+
+pub fn moderation_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(MODERATION_QUEUE_ROUTE, get(get_queue))
+        .route(MODERATION_APPROVE_ROUTE, post(approve_object))
+        .route(MODERATION_REJECT_ROUTE, post(reject_object))
+        .route(MODERATION_REVIEWS_ROUTE, get(get_reported_reviews))
+        .route(MODERATION_REVIEW_REMOVE_ROUTE, post(remove_reported_review))
+        .route(
+            MODERATION_REVIEW_DISMISS_ROUTE,
+            post(dismiss_review_reports),
+        )
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,159 @@
+use crate::limits::GB;
+use crate::limits::MB;
+use crate::models::ObjectType;
+
+// everything that differs between object types
+// adding a type only needs a variant in ObjectType and an entry here,
+// plus the s3 buckets for its packages and images
+pub struct ObjectTypeInfo {
+    // used in paths and by search filters, e.g. /world/{uuid} and is:world
+    pub name: &'static str,
+    // what paths used before the registry, e.g. /World/{uuid}, still accepted
+    pub alias: &'static str,
+    // field search results of this type are returned in
+    pub search_field: &'static str,
+    pub bucket: &'static str,
+    pub image_bucket: &'static str,
+    // content types packages can be uploaded with, the first is stored when none
+    // or the generic application/octet-stream is sent, see package_content_type
+    pub content_types: &'static [&'static str],
+    // comma separated list of sizes in bytes indexed by trust level, see limits.rs
+    pub max_sizes_env: &'static str,
+    pub default_max_sizes: [i64; 3],
+}
+
+// indexed by ObjectType
+const OBJECT_TYPES: [ObjectTypeInfo; 5] = [
+    ObjectTypeInfo {
+        name: "world",
+        alias: "World",
+        search_field: "worlds",
+        bucket: "worlds",
+        image_bucket: "worlds-images",
+        content_types: &["application/vnd.butterfly.world"],
+        max_sizes_env: "MAX_WORLD_SIZES",
+        default_max_sizes: [256 * MB, GB, 4 * GB],
+    },
+    ObjectTypeInfo {
+        name: "avatar",
+        alias: "Avatar",
+        search_field: "avatars",
+        bucket: "avatars",
+        image_bucket: "avatars-images",
+        content_types: &["application/vnd.butterfly.avatar"],
+        max_sizes_env: "MAX_AVATAR_SIZES",
+        default_max_sizes: [64 * MB, 256 * MB, 512 * MB],
+    },
+    ObjectTypeInfo {
+        name: "prop",
+        alias: "Prop",
+        search_field: "props",
+        bucket: "props",
+        image_bucket: "props-images",
+        content_types: &["application/vnd.butterfly.prop"],
+        max_sizes_env: "MAX_PROP_SIZES",
+        default_max_sizes: [32 * MB, 128 * MB, 256 * MB],
+    },
+    ObjectTypeInfo {
+        name: "shader",
+        alias: "Shader",
+        search_field: "shaders",
+        bucket: "shaders",
+        image_bucket: "shaders-images",
+        content_types: &["application/vnd.butterfly.shader"],
+        max_sizes_env: "MAX_SHADER_SIZES",
+        default_max_sizes: [8 * MB, 32 * MB, 64 * MB],
+    },
+    ObjectTypeInfo {
+        name: "audio_pack",
+        alias: "AudioPack",
+        search_field: "audio_packs",
+        bucket: "audio-packs",
+        image_bucket: "audio-packs-images",
+        content_types: &["application/vnd.butterfly.audio-pack"],
+        max_sizes_env: "MAX_AUDIO_PACK_SIZES",
+        default_max_sizes: [64 * MB, 256 * MB, GB],
+    },
+];
+
+impl ObjectType {
+    pub const ALL: [ObjectType; 5] = [
+        ObjectType::World,
+        ObjectType::Avatar,
+        ObjectType::Prop,
+        ObjectType::Shader,
+        ObjectType::AudioPack,
+    ];
+
+    pub fn info(self) -> &'static ObjectTypeInfo {
+        &OBJECT_TYPES[self as usize]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        ObjectType::ALL.into_iter().find(|x| x.info().name == name)
+    }
+}
+
+// paths take the name or the old alias
+impl TryFrom<String> for ObjectType {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        ObjectType::from_name(&name)
+            .or_else(|| ObjectType::ALL.into_iter().find(|x| x.info().alias == name))
+            .ok_or_else(|| format!("unknown object type {:?}", name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Deserialize)]
+    struct TypePath {
+        object_type: ObjectType,
+    }
+
+    fn parse(name: &str) -> Option<ObjectType> {
+        serde_json::from_value::<TypePath>(serde_json::json!({ "object_type": name }))
+            .ok()
+            .map(|x| x.object_type)
+    }
+
+    #[test]
+    fn accepts_names_and_aliases() {
+        assert_eq!(parse("world"), Some(ObjectType::World));
+        assert_eq!(parse("World"), Some(ObjectType::World));
+        assert_eq!(parse("audio_pack"), Some(ObjectType::AudioPack));
+        assert_eq!(parse("AudioPack"), Some(ObjectType::AudioPack));
+        assert_eq!(parse("audiopack"), None);
+        assert_eq!(parse("user"), None);
+    }
+
+    #[test]
+    fn registry_is_indexed_by_type() {
+        for object_type in ObjectType::ALL {
+            assert_eq!(
+                ObjectType::from_name(object_type.info().name),
+                Some(object_type)
+            );
+            assert_eq!(format!("{:?}", object_type), object_type.info().alias);
+        }
+    }
+
+    #[test]
+    fn types_have_their_own_content_types() {
+        for a in ObjectType::ALL {
+            for b in ObjectType::ALL {
+                if a != b {
+                    assert!(
+                        a.info()
+                            .content_types
+                            .iter()
+                            .all(|x| !b.info().content_types.contains(x))
+                    );
+                }
+            }
+        }
+    }
+}
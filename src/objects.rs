@@ -3,99 +3,258 @@ use crate::AppState;
 use crate::ErrorCode;
 use crate::ErrorInfo;
 use crate::auth::check_auth;
+use crate::auth::has_permission;
+use crate::collaborators::has_role;
+use crate::images;
+use crate::images::ImageSize;
+use crate::keys;
+use crate::licenses::LicenseInfo;
+use crate::licenses::LicenseUpload;
+use crate::licenses::license_for_upload;
 use crate::models;
 use crate::models::*;
+use crate::scanning::scan_package;
+use crate::schema::favourites;
 use crate::schema::licenses;
+use crate::schema::object_keys;
 use crate::schema::objects;
 use crate::schema::tags;
+use crate::schema::users;
+use crate::tags::normalize_tag;
+use crate::usage::record_use;
 use aws_sdk_s3::Client;
 use aws_sdk_s3::primitives::ByteStream;
 use axum::Extension;
 use axum::body::Body;
 use axum::extract::Path;
+use axum::extract::Query;
 use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::http::HeaderValue;
 use axum::http::StatusCode;
+use axum::http::header;
 use axum::middleware;
-use axum::{Json, Router, routing::get};
+use axum::response::IntoResponse;
+use axum::response::Response;
+use axum::{Json, Router, routing::get, routing::post};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use diesel::dsl::sql;
 use diesel::insert_into;
 use diesel::prelude::*;
+use diesel::sql_types::BigInt;
 use diesel_async::AsyncConnection;
+use diesel_async::AsyncPgConnection;
 use diesel_async::RunQueryDsl;
 use diesel_async::scoped_futures::ScopedFutureExt;
 
 use futures_util::TryStreamExt;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::Duration;
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 use tokio::io::AsyncRead;
 use tokio::io::AsyncReadExt;
+use tokio::task::spawn_blocking;
+use tracing::error;
 use uuid::Uuid;
 
+const OBJECT_CREATE_ROUTE: &str = "/{object_type}";
 const OBJECT_INFO_ROUTE: &str = "/{object_type}/{uuid}";
 const OBJECT_DOWNLOAD_ROUTE: &str = constcat::concat!(OBJECT_INFO_ROUTE, "/epck");
 const OBJECT_IMAGE_ROUTE: &str = constcat::concat!(OBJECT_INFO_ROUTE, "/image");
+const OBJECT_KEY_ROUTE: &str = constcat::concat!(OBJECT_INFO_ROUTE, "/key");
 
 #[derive(Deserialize)]
 pub struct ObjectUpload {
     name: String,
     description: String,
     tags: Vec<String>,
-    flags: Vec<bool>,
+    flags: Vec<ObjectFlag>,
     publicity: i16,
-    license: String,
-    encryption_key: Vec<u8>,
-    encryption_iv: Vec<u8>,
+    license: LicenseUpload,
 }
 
-pub async fn create_or_update_object(
+// only the fields that are set get changed
+#[derive(Deserialize)]
+pub struct ObjectPatch {
+    name: Option<String>,
+    description: Option<String>,
+    tags: Option<Vec<String>>,
+    flags: Option<Vec<ObjectFlag>>,
+    publicity: Option<i16>,
+    license: Option<LicenseUpload>,
+}
+
+impl From<ObjectUpload> for ObjectPatch {
+    fn from(value: ObjectUpload) -> Self {
+        ObjectPatch {
+            name: Some(value.name),
+            description: Some(value.description),
+            tags: Some(value.tags),
+            flags: Some(value.flags),
+            publicity: Some(value.publicity),
+            license: Some(value.license),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct CreatedObject {
+    pub id: Uuid,
+}
+
+pub async fn create_object(
+    state: State<Arc<AppState>>,
+    Path(object_type): Path<models::ObjectType>,
+    Extension(user_id): Extension<Uuid>,
+    Json(mut json): Json<ObjectUpload>,
+) -> Result<impl IntoResponse, ApiError> {
+    check_name(&json.name)?;
+    check_description(&json.description)?;
+    check_tags(&mut json.tags)?;
+
+    let mut conn = state.pool.get().await?;
+    let master_key = &state.master_key;
+    let object_id = Uuid::new_v4();
+
+    let updated_at = conn
+        .transaction(|mut conn| {
+            async move {
+                check_name_available(conn, &json.name).await?;
+                let license = license_for_upload(conn, &json.license).await?;
+
+                let now = SystemTime::now();
+                let object: Object = Object {
+                    id: object_id,
+                    name: json.name,
+                    description: json.description,
+                    flags: ObjectFlag::to_bits(&json.flags),
+                    updated_at: now,
+                    created_at: now,
+                    verified: false,
+                    object_size: 0,
+                    image_size: 0,
+                    creator: user_id,
+                    object_type: object_type as i16,
+                    publicity: json.publicity,
+                    object_sha256: Vec::new(),
+                    image_sha256: Vec::new(),
+                    rejection_reason: None,
+                    reviewed_by: None,
+                    reviewed_at: None,
+                    scan_status: ScanStatus::Pending as i16,
+                    key_version: 1,
+                    favourite_count: 0,
+                    rating_count: 0,
+                    rating_sum: 0,
+                    weekly_uses: 0,
+                    license,
+                };
+
+                diesel::insert_into(objects::table)
+                    .values(object)
+                    .execute(&mut conn)
+                    .await?;
+
+                set_tags(conn, object_id, json.tags).await?;
+
+                // the first package is encrypted with this, clients fetch it from the key route
+                keys::generate_key(conn, master_key, object_id).await?;
+
+                Ok::<_, ApiError>(now)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::ETAG, object_etag(updated_at))],
+        Json(CreatedObject { id: object_id }),
+    ))
+}
+
+// replaces every field of an object
+pub async fn replace_object(
     state: State<Arc<AppState>>,
     Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
     Extension(user_id): Extension<Uuid>,
+    headers: HeaderMap,
     Json(json): Json<ObjectUpload>,
-) -> Result<(), ApiError> {
-    let mut conn = state.pool.get().await?;
+) -> Result<impl IntoResponse, ApiError> {
+    update_object(state, object_type, object_id, user_id, headers, json.into()).await
+}
 
-    if json.name.len() < 6 || json.name.len() > 32 || json.description.len() > 4096 {
-        return Err(ApiError::WithResponse(
-            StatusCode::BAD_REQUEST,
-            Json(ErrorInfo {
-                error_code: ErrorCode::BadRequestLength,
-                error_message: Some(String::from(
-                    "Name or description was wrong length. This shouldnt happen",
-                )),
-            }),
-        ));
-    }
+pub async fn patch_object(
+    state: State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    headers: HeaderMap,
+    Json(json): Json<ObjectPatch>,
+) -> Result<impl IntoResponse, ApiError> {
+    update_object(state, object_type, object_id, user_id, headers, json).await
+}
 
-    for tag in json.tags.iter() {
-        if tag.len() < 3 || tag.len() > 32 {
-            return Err(ApiError::WithResponse(
-                StatusCode::BAD_REQUEST,
-                Json(ErrorInfo {
-                    error_code: ErrorCode::BadRequestLength,
-                    error_message: Some(format!(
-                        "Tag {:?} was wrong length. This shouldnt happen",
-                        tag
-                    )),
-                }),
-            ));
-        }
+// If-Match is optional, when its sent the update only goes through
+// if nobody else has changed the object since the client last fetched it
+async fn update_object(
+    state: State<Arc<AppState>>,
+    object_type: models::ObjectType,
+    object_id: Uuid,
+    user_id: Uuid,
+    headers: HeaderMap,
+    mut json: ObjectPatch,
+) -> Result<impl IntoResponse, ApiError> {
+    if let Some(name) = &json.name {
+        check_name(name)?;
+    }
+    if let Some(description) = &json.description {
+        check_description(description)?;
+    }
+    if let Some(tags) = &mut json.tags {
+        check_tags(tags)?;
     }
 
-    conn.transaction(|mut conn| {
-        async move {
-            if let Some(object) = objects::table
-                .select(Object::as_select())
-                .filter(objects::id.eq(&object_id))
-                .filter(objects::object_type.eq(object_type as i16))
-                .first(&mut conn)
-                .await
-                .optional()?
-            {
-                // update existing object
-                if object.creator != user_id {
+    let mut conn = state.pool.get().await?;
+
+    let updated_at = conn
+        .transaction(|mut conn| {
+            async move {
+                // locked so concurrent updates cant both pass the If-Match check
+                let Some(object) = objects::table
+                    .select(Object::as_select())
+                    .filter(objects::id.eq(&object_id))
+                    .filter(objects::object_type.eq(object_type as i16))
+                    .for_update()
+                    .first(&mut conn)
+                    .await
+                    .optional()?
+                else {
+                    return Err(ApiError::WithResponse(
+                        StatusCode::NOT_FOUND,
+                        Json(ErrorInfo {
+                            error_code: ErrorCode::DosentExist,
+                            error_message: None,
+                        }),
+                    ));
+                };
+
+                if !has_role(
+                    conn,
+                    object_id,
+                    object.creator,
+                    user_id,
+                    CollaboratorRole::Editor,
+                )
+                .await?
+                {
                     return Err(ApiError::WithResponse(
                         StatusCode::FORBIDDEN,
                         Json(ErrorInfo {
@@ -107,194 +266,322 @@ pub async fn create_or_update_object(
                     ));
                 }
 
-                let mut new_object: Object = object.clone();
-
-                new_object.name = json.name;
-                new_object.description = json.description;
-                new_object.publicity = json.publicity;
-                new_object.flags = json.flags.into_iter().map(Some).collect();
-                new_object.encryption_key = json.encryption_key;
-                new_object.encryption_iv = json.encryption_iv;
-
-                new_object.updated_at = SystemTime::now();
-
-                if let Some(license_number) = licenses::table
-                    .select(licenses::license)
-                    .filter(licenses::text.eq(&json.license))
-                    .first::<i32>(&mut conn)
-                    .await
-                    .optional()?
-                {
-                    new_object.license = license_number;
-                } else {
-                    new_object.license = insert_into(licenses::table)
-                        .values(licenses::text.eq(&json.license))
-                        .returning(licenses::license)
-                        .get_result(&mut conn)
-                        .await?;
-                }
-
-                // delete all previous tags before readding
-                // would probably be faster to get existing tags and only delete / insert the diff
-                diesel::delete(tags::table)
-                    .filter(tags::object.eq(object_id))
-                    .execute(&mut conn)
-                    .await?;
-
-                for tag in json.tags {
-                    insert_into(tags::table)
-                        .values((tags::tag.eq(tag), tags::object.eq(object_id)))
-                        .execute(&mut conn)
-                        .await?;
-                }
-
-                diesel::update(&object)
-                    .set(new_object)
-                    .execute(&mut conn)
-                    .await?;
-            } else {
-                // create new object
-
-                if objects::table
-                    .count()
-                    .filter(objects::name.eq(&json.name))
-                    .get_result::<i64>(&mut conn)
-                    .await?
-                    != 0
-                {
+                if !if_match_matches(&headers, &object_etag(object.updated_at)) {
                     return Err(ApiError::WithResponse(
-                        StatusCode::BAD_REQUEST,
+                        StatusCode::PRECONDITION_FAILED,
                         Json(ErrorInfo {
-                            error_code: ErrorCode::AlreadyExists,
+                            error_code: ErrorCode::PreconditionFailed,
                             error_message: Some(
-                                "An object with that name already exists".to_owned(),
+                                "The object has been changed since it was fetched.".to_owned(),
                             ),
                         }),
                     ));
                 }
 
-                let license: i32;
-                if let Some(license_number) = licenses::table
-                    .select(licenses::license)
-                    .filter(licenses::text.eq(&json.license))
-                    .first::<i32>(&mut conn)
-                    .await
-                    .optional()?
+                let mut new_object: Object = object.clone();
+
+                if let Some(name) = json.name
+                    && name != object.name
                 {
-                    license = license_number;
-                } else {
-                    license = insert_into(licenses::table)
-                        .values(licenses::text.eq(&json.license))
-                        .returning(licenses::license)
-                        .get_result(&mut conn)
-                        .await?;
+                    check_name_available(conn, &name).await?;
+                    new_object.name = name;
+                }
+                if let Some(description) = json.description {
+                    new_object.description = description;
+                }
+                if let Some(flags) = json.flags {
+                    new_object.flags = ObjectFlag::to_bits(&flags);
+                }
+                if let Some(publicity) = json.publicity {
+                    new_object.publicity = publicity;
+                }
+                if let Some(license) = json.license {
+                    new_object.license = license_for_upload(conn, &license).await?;
+                }
+                if let Some(tags) = json.tags {
+                    set_tags(conn, object_id, tags).await?;
                 }
 
-                let object: Object = Object {
-                    id: object_id,
-                    name: json.name,
-                    description: json.description,
-                    flags: json.flags.into_iter().map(Some).collect(),
-                    updated_at: SystemTime::now(),
-                    created_at: SystemTime::now(),
-                    verified: false,
-                    object_size: 0,
-                    image_size: 0,
-                    creator: user_id,
-                    object_type: object_type as i16,
-                    publicity: json.publicity,
-                    encryption_key: json.encryption_key,
-                    encryption_iv: json.encryption_iv,
-                    license,
-                };
+                let updated_at = SystemTime::now();
+                new_object.updated_at = updated_at;
 
-                diesel::insert_into(objects::table)
-                    .values(object)
+                diesel::update(&object)
+                    .set(new_object)
                     .execute(&mut conn)
                     .await?;
 
-                for tag in json.tags {
-                    insert_into(tags::table)
-                        .values((tags::tag.eq(tag), tags::object.eq(object_id)))
-                        .execute(&mut conn)
-                        .await?;
-                }
+                Ok(updated_at)
             }
+            .scope_boxed()
+        })
+        .await?;
 
-            Ok(())
+    Ok([(header::ETAG, object_etag(updated_at))])
+}
+
+fn check_name(name: &str) -> Result<(), ApiError> {
+    if name.len() < 6 || name.len() > 32 {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::BadRequestLength,
+                error_message: Some(String::from("Name was wrong length. This shouldnt happen")),
+            }),
+        ));
+    }
+    Ok(())
+}
+
+fn check_description(description: &str) -> Result<(), ApiError> {
+    if description.len() > 4096 {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::BadRequestLength,
+                error_message: Some(String::from(
+                    "Description was wrong length. This shouldnt happen",
+                )),
+            }),
+        ));
+    }
+    Ok(())
+}
+
+// tags are stored normalized so searching for them dosent depend on how they were typed
+fn check_tags(tags: &mut [String]) -> Result<(), ApiError> {
+    for tag in tags.iter_mut() {
+        *tag = normalize_tag(tag);
+        if tag.len() < 3 || tag.len() > 32 {
+            return Err(ApiError::WithResponse(
+                StatusCode::BAD_REQUEST,
+                Json(ErrorInfo {
+                    error_code: ErrorCode::BadRequestLength,
+                    error_message: Some(format!(
+                        "Tag {:?} was wrong length. This shouldnt happen",
+                        tag
+                    )),
+                }),
+            ));
         }
-        .scope_boxed()
-    })
-    .await
+    }
+    Ok(())
+}
+
+async fn check_name_available(conn: &mut AsyncPgConnection, name: &str) -> Result<(), ApiError> {
+    if objects::table
+        .count()
+        .filter(objects::name.eq(name))
+        .get_result::<i64>(conn)
+        .await?
+        != 0
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::AlreadyExists,
+                error_message: Some("An object with that name already exists".to_owned()),
+            }),
+        ));
+    }
+    Ok(())
+}
+
+// only inserts and deletes the tags that actually changed
+async fn set_tags(
+    conn: &mut AsyncPgConnection,
+    object_id: Uuid,
+    new_tags: Vec<String>,
+) -> QueryResult<()> {
+    let new_tags: HashSet<String> = new_tags.into_iter().collect();
+    let old_tags: HashSet<String> = tags::table
+        .select(tags::tag)
+        .filter(tags::object.eq(object_id))
+        .load::<String>(conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    let removed: Vec<&String> = old_tags.difference(&new_tags).collect();
+    if !removed.is_empty() {
+        diesel::delete(tags::table)
+            .filter(tags::object.eq(object_id))
+            .filter(tags::tag.eq_any(removed))
+            .execute(conn)
+            .await?;
+    }
+
+    let added: Vec<_> = new_tags
+        .difference(&old_tags)
+        .map(|tag| (tags::tag.eq(tag), tags::object.eq(object_id)))
+        .collect();
+    if !added.is_empty() {
+        insert_into(tags::table).values(added).execute(conn).await?;
+    }
+
+    Ok(())
+}
+
+pub fn to_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap().as_secs()
 }
 
+// the public parts of an object, used anywhere a list of objects is returned
 #[derive(Serialize)]
-pub struct ObjectInfo {
+pub struct ObjectSummary {
     pub id: Uuid,
     pub name: String,
     pub description: String,
-    pub flags: Vec<bool>,
+    pub flags: Vec<ObjectFlag>,
     pub updated_at: u64,
     pub created_at: u64,
     pub object_size: i64,
     pub image_size: i64,
     pub creator: Uuid,
+    pub creator_username: String,
     pub object_type: i16,
-    pub publicity: i16,
-    pub license: i32,
-    pub encryption_key: Vec<u8>,
-    pub encryption_iv: Vec<u8>,
+    pub license: LicenseInfo,
+    pub verified: bool,
+    pub favourite_count: i32,
+    pub rating_count: i32,
+    // average of every rating, none if it hasnt been rated yet
+    pub rating: Option<f64>,
+    // unique users per day over the last 7 days, updated hourly
+    pub weekly_uses: i32,
     pub tags: Vec<String>,
 }
 
+impl ObjectSummary {
+    pub fn new(
+        object: &Object,
+        creator_username: String,
+        license: LicenseInfo,
+        tags: Vec<String>,
+    ) -> ObjectSummary {
+        ObjectSummary {
+            id: object.id,
+            name: object.name.clone(),
+            description: object.description.clone(),
+            flags: ObjectFlag::from_bits(object.flags),
+            updated_at: to_secs(object.updated_at),
+            created_at: to_secs(object.created_at),
+            object_size: object.object_size,
+            image_size: object.image_size,
+            creator: object.creator,
+            creator_username,
+            object_type: object.object_type,
+            license,
+            verified: object.verified,
+            favourite_count: object.favourite_count,
+            rating_count: object.rating_count,
+            rating: (object.rating_count > 0)
+                .then(|| object.rating_sum as f64 / object.rating_count as f64),
+            weekly_uses: object.weekly_uses,
+            tags,
+        }
+    }
+}
+
+// the tags of every object in a list, in one query
+pub async fn load_tags(
+    conn: &mut AsyncPgConnection,
+    object_ids: &[Uuid],
+) -> QueryResult<HashMap<Uuid, Vec<String>>> {
+    let mut tags_by_object: HashMap<Uuid, Vec<String>> = HashMap::new();
+    if object_ids.is_empty() {
+        return Ok(tags_by_object);
+    }
+    for (object, tag) in tags::table
+        .select((tags::object, tags::tag))
+        .filter(tags::object.eq_any(object_ids))
+        .order(tags::tag.asc())
+        .load::<(Uuid, String)>(conn)
+        .await?
+    {
+        tags_by_object.entry(object).or_default().push(tag);
+    }
+    Ok(tags_by_object)
+}
+
+#[derive(Serialize)]
+pub struct ObjectInfo {
+    #[serde(flatten)]
+    pub summary: ObjectSummary,
+    pub publicity: i16,
+    // 0 if the package isnt encrypted
+    pub key_version: i32,
+    // hex encoded like the etag, empty until something has been uploaded
+    pub object_sha256: String,
+    // of the original image as its served, which is re-encoded from what was uploaded
+    pub image_sha256: String,
+    pub scan_status: i16,
+    // whether the user asking has favourited it
+    pub favourited: bool,
+    // only sent to the creator
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejection_reason: Option<String>,
+}
+
 pub async fn get_object_info(
     state: State<Arc<AppState>>,
     Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
-) -> Result<Json<ObjectInfo>, ApiError> {
+    Extension(user_id): Extension<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
     let mut conn = state.pool.get().await?;
 
-    if let Some(object) = objects::table
-        .select(Object::as_select())
+    if let Some((object, license, creator_username)) = objects::table
+        .inner_join(licenses::table)
+        .inner_join(users::table.on(users::id.eq(objects::creator)))
+        .select((
+            Object::as_select(),
+            LicenseInfo::as_select(),
+            users::username,
+        ))
         .filter(objects::id.eq(&object_id))
         .filter(objects::object_type.eq(object_type as i16))
-        .first::<Object>(&mut conn)
+        .first::<(Object, LicenseInfo, String)>(&mut conn)
         .await
         .optional()?
+        && !is_hidden(
+            &state,
+            &mut conn,
+            object.id,
+            object.verified,
+            object.creator,
+            user_id,
+        )
+        .await?
     {
         let tags = tags::table
             .select(tags::tag)
             .filter(tags::object.eq(object.id))
+            .order(tags::tag.asc())
             .load(&mut conn)
             .await?;
-        Ok(Json(ObjectInfo {
-            id: object.id,
-            name: object.name,
-            description: object.description,
-            flags: object
-                .flags
-                .iter()
-                .map(|x| if let Some(x) = x { *x } else { false })
-                .collect::<Vec<bool>>(),
-            updated_at: object
-                .updated_at
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            created_at: object
-                .created_at
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
-            object_size: object.object_size,
-            image_size: object.image_size,
-            creator: object.creator,
-            object_type: object.object_type,
-            publicity: object.publicity,
-            license: object.license,
-            encryption_iv: object.encryption_iv,
-            encryption_key: object.encryption_key,
-            tags,
-        }))
+        let favourited = favourites::table
+            .count()
+            .filter(favourites::user.eq(user_id))
+            .filter(favourites::object.eq(object.id))
+            .get_result::<i64>(&mut conn)
+            .await?
+            != 0;
+        let etag = object_etag(object.updated_at);
+        Ok((
+            [(header::ETAG, etag)],
+            Json(ObjectInfo {
+                summary: ObjectSummary::new(&object, creator_username, license, tags),
+                publicity: object.publicity,
+                key_version: object.key_version,
+                object_sha256: hex::encode(object.object_sha256),
+                image_sha256: hex::encode(object.image_sha256),
+                scan_status: object.scan_status,
+                favourited,
+                rejection_reason: if object.creator == user_id {
+                    object.rejection_reason
+                } else {
+                    None
+                },
+            }),
+        ))
     } else {
         Err(ApiError::WithResponse(
             StatusCode::NOT_FOUND,
@@ -309,29 +596,173 @@ pub async fn get_object_info(
 pub async fn get_object_file(
     state: State<Arc<AppState>>,
     Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
-) -> Result<Body, ApiError> {
-    let enum_str: &'static str = match object_type {
-        ObjectType::World => "worlds",
-        ObjectType::Avatar => "avatars",
+    Extension(user_id): Extension<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let Some((checksum, uploaded_at, access)) = objects::table
+        .select((
+            objects::object_sha256,
+            objects::object_uploaded_at,
+            PackageAccess::as_select(),
+        ))
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .first::<(Vec<u8>, SystemTime, PackageAccess)>(&mut conn)
+        .await
+        .optional()?
+    else {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
     };
 
-    let object = state
-        .s3_client
-        .get_object()
-        .bucket(enum_str.to_owned())
-        .key(object_id.to_string())
-        .send()
-        .await?;
-    let x = object.body.into_async_read();
-    Ok(Body::from_stream(tokio_util::io::ReaderStream::new(x)))
+    check_can_download(&state, &mut conn, object_id, &access, user_id).await?;
+
+    let response = stream_from_s3(
+        &state.s3_client,
+        object_type.info().bucket,
+        &object_id.to_string(),
+        &headers,
+        checksum_etag(&checksum, None),
+        (!checksum.is_empty()).then_some(checksum.as_slice()),
+        uploaded_at,
+    )
+    .await?;
+
+    // not modified responses still count, the client is using its cached copy
+    // a failed count shouldnt fail the download
+    if let Err(err) = record_use(
+        &mut conn,
+        object_id,
+        access.creator,
+        user_id,
+        UseKind::Download,
+    )
+    .await
+    {
+        error!("failed to record a download of {}: {:?}", object_id, err);
+    }
+    Ok(response)
+}
+
+// only users who can download the package get the key for it
+pub async fn get_object_key(
+    state: State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let Some((key_version, access)) = objects::table
+        .select((objects::key_version, PackageAccess::as_select()))
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .first::<(i32, PackageAccess)>(&mut conn)
+        .await
+        .optional()?
+    else {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
+    };
+
+    check_can_download(&state, &mut conn, object_id, &access, user_id).await?;
+
+    if key_version == keys::UNENCRYPTED_VERSION {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: Some(
+                    "This object isnt encrypted, rotate its key to start encrypting it.".to_owned(),
+                ),
+            }),
+        ));
+    }
+
+    let Some(key) = keys::get_key(&mut conn, &state.master_key, object_id, key_version).await?
+    else {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: Some("This object has no encryption key.".to_owned()),
+            }),
+        ));
+    };
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(key)))
+}
+
+// generates a new key to encrypt the next package with
+// downloads keep using the current key until a package is uploaded with the new key-version
+pub async fn rotate_object_key(
+    state: State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<impl IntoResponse, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let Some(creator) = objects::table
+        .select(objects::creator)
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .first::<Uuid>(&mut conn)
+        .await
+        .optional()?
+    else {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
+    };
+
+    if !has_role(
+        &mut conn,
+        object_id,
+        creator,
+        user_id,
+        CollaboratorRole::Uploader,
+    )
+    .await?
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some("You do not have permission to edit this object.".to_owned()),
+            }),
+        ));
+    }
+
+    let key = keys::generate_key(&mut conn, &state.master_key, object_id).await?;
+
+    Ok(([(header::CACHE_CONTROL, "no-store")], Json(key)))
 }
 
 pub async fn change_object_file(
     state: State<Arc<AppState>>,
     Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
     Extension(user_id): Extension<Uuid>,
+    headers: HeaderMap,
     body: Body,
 ) -> Result<(), ApiError> {
+    let expected_checksum = expected_checksum(&headers)?;
+    let key_version = key_version(&headers)?;
+    let content_type = package_content_type(object_type, &headers)?;
     let mut conn = state.pool.get().await?;
 
     if let Some(object) = objects::table
@@ -342,7 +773,15 @@ pub async fn change_object_file(
         .await
         .optional()?
     {
-        if object.creator != user_id {
+        if !has_role(
+            &mut conn,
+            object_id,
+            object.creator,
+            user_id,
+            CollaboratorRole::Uploader,
+        )
+        .await?
+        {
             return Err(ApiError::WithResponse(
                 StatusCode::FORBIDDEN,
                 Json(ErrorInfo {
@@ -354,49 +793,85 @@ pub async fn change_object_file(
             ));
         }
 
-        let stream = body.into_data_stream();
-
-        let enum_str: &'static str = match object_type {
-            ObjectType::World => "worlds",
-            ObjectType::Avatar => "avatars",
-        };
+        // packages are encrypted with the current key unless a newer one is given
+        // unencrypted objects can stay that way, but encrypted ones cant go back to it
+        let key_version = key_version.unwrap_or(object.key_version);
+        let unencrypted = key_version == keys::UNENCRYPTED_VERSION
+            && object.key_version == keys::UNENCRYPTED_VERSION;
+        if !unencrypted
+            && object_keys::table
+                .count()
+                .filter(object_keys::object.eq(object_id))
+                .filter(object_keys::version.eq(key_version))
+                .get_result::<i64>(&mut conn)
+                .await?
+                == 0
+        {
+            return Err(ApiError::WithResponse(
+                StatusCode::BAD_REQUEST,
+                Json(ErrorInfo {
+                    error_code: ErrorCode::InvalidRequest,
+                    error_message: Some("That key version dosent exist.".to_owned()),
+                }),
+            ));
+        }
 
-        diesel::update(objects::table)
-            .filter(objects::id.eq(&object_id))
-            .filter(objects::object_type.eq(object_type as i16))
-            .set((
-                objects::verified.eq(false),
-                objects::updated_at.eq(SystemTime::now()),
-            ))
-            .execute(&mut conn)
+        // uploads from collaborators count against the owners quota
+        let trust = users::table
+            .select(users::trust)
+            .filter(users::id.eq(object.creator))
+            .first::<i32>(&mut conn)
             .await?;
+        // the old file is replaced so it shouldnt count against the quota
+        let remaining_storage = state.upload_limits.storage_quota(trust)
+            - (storage_used(object.creator, &mut conn).await? - object.object_size);
+        let max_size = state
+            .upload_limits
+            .max_object_size(object_type, trust)
+            .min(remaining_storage);
 
-        upload_object_stream(
+        let stream = body.into_data_stream();
+
+        let uploaded = upload_object_stream(
             &state.s3_client,
-            enum_str,
+            object_type.info().bucket,
             &object_id.to_string(),
             &mut tokio_util::io::StreamReader::new(stream.map_err(|_| {
                 std::io::Error::new(std::io::ErrorKind::NotFound, "no error handling here")
             })),
+            max_size,
+            expected_checksum.as_deref(),
+            content_type,
         )
         .await?;
 
+        // the new package needs scanning and reviewing again,
+        // but only once its actually been stored so a rejected upload changes nothing
+        let now = SystemTime::now();
         diesel::update(objects::table)
             .filter(objects::id.eq(&object_id))
             .filter(objects::object_type.eq(object_type as i16))
-            .set(
-                objects::object_size.eq(state
-                    .s3_client
-                    .head_object()
-                    .bucket(enum_str)
-                    .key(&object_id.to_string())
-                    .send()
-                    .await?
-                    .content_length()
-                    .unwrap_or_default()),
-            )
+            .set((
+                objects::object_size.eq(uploaded.size),
+                objects::object_sha256.eq(&uploaded.sha256),
+                objects::key_version.eq(key_version),
+                objects::verified.eq(false),
+                objects::rejection_reason.eq(None::<String>),
+                objects::scan_status.eq(ScanStatus::Pending as i16),
+                objects::updated_at.eq(now),
+                objects::object_uploaded_at.eq(now),
+            ))
             .execute(&mut conn)
             .await?;
+
+        // scanning can take a while so dont make the client wait for it
+        tokio::spawn(scan_package(
+            state.0.clone(),
+            object_id,
+            object_type.info().bucket,
+            uploaded.sha256,
+            uploaded.size,
+        ));
     } else {
         return Err(ApiError::WithResponse(
             StatusCode::NOT_FOUND,
@@ -410,32 +885,82 @@ pub async fn change_object_file(
     Ok(())
 }
 
+#[derive(Deserialize)]
+pub struct ImageQuery {
+    #[serde(default)]
+    size: ImageSize,
+}
+
 pub async fn get_object_image(
     state: State<Arc<AppState>>,
     Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
-) -> Result<Body, ApiError> {
-    let enum_str: &'static str = match object_type {
-        ObjectType::World => "worlds",
-        ObjectType::Avatar => "avatars",
+    Query(query): Query<ImageQuery>,
+    Extension(user_id): Extension<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let Some((checksum, uploaded_at, verified, creator)) = objects::table
+        .select((
+            objects::image_sha256,
+            objects::image_uploaded_at,
+            objects::verified,
+            objects::creator,
+        ))
+        .filter(objects::id.eq(&object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .first::<(Vec<u8>, SystemTime, bool, Uuid)>(&mut conn)
+        .await
+        .optional()?
+    else {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
     };
 
-    let object = state
-        .s3_client
-        .get_object()
-        .bucket(enum_str.to_owned() + "-images")
-        .key(object_id.to_string())
-        .send()
-        .await?;
-    let x = object.body.into_async_read();
-    Ok(Body::from_stream(tokio_util::io::ReaderStream::new(x)))
+    if is_hidden(&state, &mut conn, object_id, verified, creator, user_id).await? {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
+    }
+
+    // thumbnails dont have their own checksum so they only get an etag
+    let (etag, digest) = match query.size {
+        ImageSize::Original => (
+            checksum_etag(&checksum, None),
+            (!checksum.is_empty()).then_some(checksum.as_slice()),
+        ),
+        size => (checksum_etag(&checksum, Some(size.name())), None),
+    };
+
+    stream_from_s3(
+        &state.s3_client,
+        object_type.info().image_bucket,
+        &query.size.key(object_id),
+        &headers,
+        etag,
+        digest,
+        uploaded_at,
+    )
+    .await
 }
 
 pub async fn change_object_image(
     state: State<Arc<AppState>>,
     Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
     Extension(user_id): Extension<Uuid>,
+    headers: HeaderMap,
     body: Body,
 ) -> Result<(), ApiError> {
+    let expected_checksum = expected_checksum(&headers)?;
     let mut conn = state.pool.get().await?;
 
     if let Some(object) = objects::table
@@ -446,7 +971,15 @@ pub async fn change_object_image(
         .await
         .optional()?
     {
-        if object.creator != user_id {
+        if !has_role(
+            &mut conn,
+            object_id,
+            object.creator,
+            user_id,
+            CollaboratorRole::Uploader,
+        )
+        .await?
+        {
             return Err(ApiError::WithResponse(
                 StatusCode::FORBIDDEN,
                 Json(ErrorInfo {
@@ -458,47 +991,100 @@ pub async fn change_object_image(
             ));
         }
 
+        // uploads from collaborators count against the owners quota
+        let trust = users::table
+            .select(users::trust)
+            .filter(users::id.eq(object.creator))
+            .first::<i32>(&mut conn)
+            .await?;
+        // the old image is replaced so it shouldnt count against the quota
+        let remaining_storage = state.upload_limits.storage_quota(trust)
+            - (storage_used(object.creator, &mut conn).await? - object.image_size);
+        let max_size = state
+            .upload_limits
+            .max_image_size(trust)
+            .min(remaining_storage);
+
         let stream = body.into_data_stream();
 
-        let enum_str: &'static str = match object_type {
-            ObjectType::World => "worlds",
-            ObjectType::Avatar => "avatars",
+        let data = read_limited(
+            &mut tokio_util::io::StreamReader::new(stream.map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "no error handling here")
+            })),
+            max_size,
+        )
+        .await?;
+
+        if expected_checksum.is_some_and(|x| x != Sha256::digest(&data).as_slice()) {
+            return Err(checksum_mismatch());
+        }
+
+        let image = match spawn_blocking(move || images::process_image(&data)).await? {
+            Ok(image) => image,
+            Err(error) => {
+                return Err(ApiError::WithResponse(
+                    StatusCode::BAD_REQUEST,
+                    Json(ErrorInfo {
+                        error_code: ErrorCode::InvalidImage,
+                        error_message: Some(error.to_string()),
+                    }),
+                ));
+            }
         };
 
-        diesel::update(objects::table)
-            .filter(objects::id.eq(&object_id))
-            .filter(objects::object_type.eq(object_type as i16))
-            .set((
-                objects::verified.eq(false),
-                objects::updated_at.eq(SystemTime::now()),
-            ))
-            .execute(&mut conn)
+        let bucket = object_type.info().image_bucket;
+
+        // re-encoding can make the image bigger and thumbnails take up space too,
+        // so everything thats stored has to fit in the quota before any of it is uploaded
+        let image_size = image.original.len() as i64
+            + image
+                .thumbnails
+                .iter()
+                .map(|(_, thumbnail)| thumbnail.len() as i64)
+                .sum::<i64>();
+        if image_size > remaining_storage {
+            return Err(upload_too_large(remaining_storage));
+        }
+
+        for (size, thumbnail) in image.thumbnails {
+            upload_object_stream(
+                &state.s3_client,
+                bucket,
+                &size.key(object_id),
+                &mut thumbnail.as_slice(),
+                remaining_storage,
+                None,
+                image.content_type,
+            )
             .await?;
+        }
 
-        upload_object_stream(
+        // the checksum is of the re-encoded original thats served to clients,
+        // not of what was uploaded, so downloads can be checked against it
+        let uploaded = upload_object_stream(
             &state.s3_client,
-            &(enum_str.to_owned() + "-images"),
-            &object_id.to_string(),
-            &mut tokio_util::io::StreamReader::new(stream.map_err(|_| {
-                std::io::Error::new(std::io::ErrorKind::NotFound, "no error handling here")
-            })),
+            bucket,
+            &ImageSize::Original.key(object_id),
+            &mut image.original.as_slice(),
+            remaining_storage,
+            None,
+            image.content_type,
         )
         .await?;
 
+        // a new image needs reviewing again too
+        let now = SystemTime::now();
         diesel::update(objects::table)
             .filter(objects::id.eq(&object_id))
             .filter(objects::object_type.eq(object_type as i16))
-            .set(
-                objects::image_size.eq(state
-                    .s3_client
-                    .head_object()
-                    .bucket(&(enum_str.to_owned() + "-images"))
-                    .key(&object_id.to_string())
-                    .send()
-                    .await?
-                    .content_length()
-                    .unwrap_or_default()),
-            )
+            .set((
+                objects::image_size.eq(image_size),
+                objects::image_sha256.eq(uploaded.sha256),
+                objects::verified.eq(false),
+                objects::rejection_reason.eq(None::<String>),
+                objects::updated_at.eq(now),
+                objects::image_uploaded_at.eq(now),
+            ))
             .execute(&mut conn)
             .await?;
     } else {
@@ -514,12 +1100,466 @@ pub async fn change_object_image(
     Ok(())
 }
 
+// when hide_unverified is set, objects that havent been verified can only be
+// seen by their creator, their collaborators and verifiers reviewing them
+pub async fn is_hidden(
+    state: &AppState,
+    conn: &mut AsyncPgConnection,
+    object_id: Uuid,
+    verified: bool,
+    creator: Uuid,
+    user_id: Uuid,
+) -> QueryResult<bool> {
+    Ok(state.hide_unverified
+        && !verified
+        && !has_role(conn, object_id, creator, user_id, CollaboratorRole::Viewer).await?
+        && !has_permission(conn, user_id, Permission::Verifier).await?)
+}
+
+// private objects are only visible to their creator, collaborators and verifiers,
+// and so are unverified ones if hide_unverified is set
+// privileged is whether the user is one of those
+pub fn is_visible(publicity: i16, verified: bool, hide_unverified: bool, privileged: bool) -> bool {
+    privileged || (publicity == Publicity::Public as i16 && (verified || !hide_unverified))
+}
+
+// is_visible for a single object, lists should filter with visible_objects_filter! instead
+pub async fn can_view(
+    state: &AppState,
+    conn: &mut AsyncPgConnection,
+    object_id: Uuid,
+    verified: bool,
+    creator: Uuid,
+    publicity: i16,
+    user_id: Uuid,
+) -> QueryResult<bool> {
+    if is_visible(publicity, verified, state.hide_unverified, false) {
+        return Ok(true);
+    }
+    Ok(
+        has_role(conn, object_id, creator, user_id, CollaboratorRole::Viewer).await?
+            || has_permission(conn, user_id, Permission::Verifier).await?,
+    )
+}
+
+// finds an object the user is allowed to see, returns its creator
+// the type is only checked if theres one in the path
+pub async fn find_visible_object(
+    state: &AppState,
+    conn: &mut AsyncPgConnection,
+    object_type: Option<ObjectType>,
+    object_id: Uuid,
+    user_id: Uuid,
+) -> Result<Uuid, ApiError> {
+    let mut query = objects::table
+        .select((objects::verified, objects::creator, objects::publicity))
+        .filter(objects::id.eq(object_id))
+        .into_boxed();
+    if let Some(object_type) = object_type {
+        query = query.filter(objects::object_type.eq(object_type as i16));
+    }
+    match query.first::<(bool, Uuid, i16)>(conn).await.optional()? {
+        Some((verified, creator, publicity))
+            if can_view(
+                state, conn, object_id, verified, creator, publicity, user_id,
+            )
+            .await? =>
+        {
+            Ok(creator)
+        }
+        _ => Err(ApiError::not_found()),
+    }
+}
+
+// is_visible as a filter on a boxed query over objects, verifier is whether the user is one
+macro_rules! visible_objects_filter {
+    ($query:ident, $user_id:expr, $verifier:expr, $hide_unverified:expr) => {
+        if !$verifier {
+            let collaborating = objects::creator.eq($user_id).or(objects::id.eq_any(
+                object_collaborators::table
+                    .select(object_collaborators::object)
+                    .filter(object_collaborators::user.eq($user_id)),
+            ));
+            $query = $query.filter(
+                objects::publicity
+                    .eq(Publicity::Public as i16)
+                    .or(collaborating),
+            );
+            if $hide_unverified {
+                $query = $query.filter(objects::verified.or(collaborating));
+            }
+        }
+    };
+}
+pub(crate) use visible_objects_filter;
+
+// everything about an object that decides who can download its package
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = objects)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub(crate) struct PackageAccess {
+    verified: bool,
+    pub(crate) creator: Uuid,
+    publicity: i16,
+    scan_status: i16,
+}
+
+// the creator, collaborators and verifiers can download anything that isnt quarantined,
+// everyone else only gets public packages that have been verified and scanned clean
+// quarantined packages are only downloadable by verifiers, not even the creator
+pub(crate) async fn check_can_download(
+    state: &AppState,
+    conn: &mut AsyncPgConnection,
+    object_id: Uuid,
+    access: &PackageAccess,
+    user_id: Uuid,
+) -> Result<(), ApiError> {
+    let verifier = has_permission(conn, user_id, Permission::Verifier).await?;
+
+    if access.scan_status == ScanStatus::Quarantined as i16 && !verifier {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some("This package has been quarantined.".to_owned()),
+            }),
+        ));
+    }
+
+    if verifier
+        || has_role(
+            conn,
+            object_id,
+            access.creator,
+            user_id,
+            CollaboratorRole::Viewer,
+        )
+        .await?
+    {
+        return Ok(());
+    }
+
+    if access.publicity != Publicity::Public as i16 || (state.hide_unverified && !access.verified) {
+        return Err(ApiError::WithResponse(
+            StatusCode::NOT_FOUND,
+            Json(ErrorInfo {
+                error_code: ErrorCode::DosentExist,
+                error_message: None,
+            }),
+        ));
+    }
+
+    if !access.verified || access.scan_status != ScanStatus::Clean as i16 {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some("This package hasnt been verified yet.".to_owned()),
+            }),
+        ));
+    }
+
+    Ok(())
+}
+
+// total size in bytes of every object and image a user has uploaded
+pub async fn storage_used(user_id: Uuid, conn: &mut AsyncPgConnection) -> QueryResult<i64> {
+    objects::table
+        .select(sql::<BigInt>(
+            "COALESCE(SUM(object_size + image_size), 0)::BIGINT",
+        ))
+        .filter(objects::creator.eq(user_id))
+        .first(conn)
+        .await
+}
+
+// reads a whole stream into memory, rejecting it as soon as more than max_size bytes are read
+async fn read_limited<S: AsyncRead + Unpin + Send>(
+    stream: &mut S,
+    max_size: i64,
+) -> Result<Vec<u8>, ApiError> {
+    let mut data = Vec::new();
+    // read one byte past the limit so we can tell if it was exceeded
+    stream
+        .take(max_size.max(0) as u64 + 1)
+        .read_to_end(&mut data)
+        .await?;
+    if data.len() as i64 > max_size {
+        return Err(upload_too_large(max_size));
+    }
+    Ok(data)
+}
+
+// objects uploaded before checksums were recorded dont have one
+fn checksum_etag(checksum: &[u8], variant: Option<&str>) -> Option<String> {
+    if checksum.is_empty() {
+        return None;
+    }
+    Some(match variant {
+        Some(variant) => format!("\"{}-{}\"", hex::encode(checksum), variant),
+        None => format!("\"{}\"", hex::encode(checksum)),
+    })
+}
+
+// updated_at changes whenever the object or its files do
+// postgres timestamps have microsecond precision so thats all the etag uses
+fn object_etag(updated_at: SystemTime) -> String {
+    format!(
+        "\"{}\"",
+        updated_at.duration_since(UNIX_EPOCH).unwrap().as_micros()
+    )
+}
+
+// a missing If-Match always matches, weak etags never do
+fn if_match_matches(request_headers: &HeaderMap, etag: &str) -> bool {
+    let Some(if_match) = request_headers
+        .get(header::IF_MATCH)
+        .and_then(|x| x.to_str().ok())
+    else {
+        return request_headers.get(header::IF_MATCH).is_none();
+    };
+    if_match.split(',').any(|x| {
+        let x = x.trim();
+        x == "*" || x == etag
+    })
+}
+
+// http dates only have second precision
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+}
+
+// If-None-Match takes precedence over If-Modified-Since when both are sent
+fn is_not_modified(
+    request_headers: &HeaderMap,
+    etag: Option<&str>,
+    uploaded_at: SystemTime,
+) -> bool {
+    if let Some(if_none_match) = request_headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|x| x.to_str().ok())
+    {
+        return if_none_match.split(',').any(|x| {
+            let x = x.trim();
+            x == "*" || etag.is_some_and(|etag| x.trim_start_matches("W/") == etag)
+        });
+    }
+    if let Some(since) = request_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| httpdate::parse_http_date(x).ok())
+    {
+        return truncate_to_secs(uploaded_at) <= since;
+    }
+    false
+}
+
+// a range request with an If-Range that dosent match the current version gets the full body
+fn if_range_matches(
+    request_headers: &HeaderMap,
+    etag: Option<&str>,
+    uploaded_at: SystemTime,
+) -> bool {
+    let Some(if_range) = request_headers
+        .get(header::IF_RANGE)
+        .and_then(|x| x.to_str().ok())
+    else {
+        return true;
+    };
+    if if_range.starts_with('"') {
+        etag == Some(if_range)
+    } else {
+        httpdate::parse_http_date(if_range).is_ok_and(|x| x == truncate_to_secs(uploaded_at))
+    }
+}
+
+// serves an object from s3 handling conditional and range requests
+async fn stream_from_s3(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    request_headers: &HeaderMap,
+    etag: Option<String>,
+    digest: Option<&[u8]>,
+    uploaded_at: SystemTime,
+) -> Result<Response, ApiError> {
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.insert(
+        header::LAST_MODIFIED,
+        httpdate::fmt_http_date(uploaded_at).parse()?,
+    );
+    if let Some(etag) = &etag {
+        headers.insert(header::ETAG, etag.parse()?);
+    }
+    if let Some(digest) = digest {
+        headers.insert(
+            "digest",
+            format!("sha-256={}", BASE64.encode(digest)).parse()?,
+        );
+    }
+
+    if is_not_modified(request_headers, etag.as_deref(), uploaded_at) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let mut request = client.get_object().bucket(bucket).key(key);
+    if let Some(range) = request_headers
+        .get(header::RANGE)
+        .and_then(|x| x.to_str().ok())
+        && if_range_matches(request_headers, etag.as_deref(), uploaded_at)
+    {
+        request = request.range(range);
+    }
+
+    let object = match request.send().await {
+        Ok(object) => object,
+        Err(error) => {
+            return match error.raw_response().map(|x| x.status().as_u16()) {
+                Some(404) => Err(ApiError::WithResponse(
+                    StatusCode::NOT_FOUND,
+                    Json(ErrorInfo {
+                        error_code: ErrorCode::DosentExist,
+                        error_message: Some("Nothing has been uploaded yet.".to_owned()),
+                    }),
+                )),
+                Some(416) => Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response()),
+                _ => Err(error.into()),
+            };
+        }
+    };
+
+    let status = if let Some(content_range) = object.content_range() {
+        headers.insert(header::CONTENT_RANGE, content_range.parse()?);
+        StatusCode::PARTIAL_CONTENT
+    } else {
+        StatusCode::OK
+    };
+    if let Some(content_length) = object.content_length() {
+        headers.insert(header::CONTENT_LENGTH, content_length.into());
+    }
+    headers.insert(
+        header::CONTENT_TYPE,
+        object
+            .content_type()
+            .unwrap_or("application/octet-stream")
+            .parse()?,
+    );
+
+    let x = object.body.into_async_read();
+    Ok((
+        status,
+        headers,
+        Body::from_stream(tokio_util::io::ReaderStream::new(x)),
+    )
+        .into_response())
+}
+
+// clients can send the hex encoded sha256 of an upload in the checksum header
+// to have it rejected if what we receive dosent match
+fn expected_checksum(headers: &HeaderMap) -> Result<Option<Vec<u8>>, ApiError> {
+    let Some(checksum) = headers.get("checksum") else {
+        return Ok(None);
+    };
+    match hex::decode(checksum) {
+        Ok(checksum) if checksum.len() == 32 => Ok(Some(checksum)),
+        _ => Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some(
+                    "Checksum header must be a hex encoded sha256 hash.".to_owned(),
+                ),
+            }),
+        )),
+    }
+}
+
+// packages without a content type get the default one for their object type
+fn package_content_type(
+    object_type: ObjectType,
+    headers: &HeaderMap,
+) -> Result<&'static str, ApiError> {
+    let content_types = object_type.info().content_types;
+    // older clients dont send a type or send a generic one
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .filter(|x| *x != "application/octet-stream")
+    else {
+        return Ok(content_types[0]);
+    };
+    content_types
+        .iter()
+        .find(|x| content_type == **x)
+        .copied()
+        .ok_or(ApiError::WithResponse(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some(format!(
+                    "Packages must be uploaded as one of {}.",
+                    content_types.join(", ")
+                )),
+            }),
+        ))
+}
+
+// the key version a package was encrypted with, from the key-version header
+fn key_version(headers: &HeaderMap) -> Result<Option<i32>, ApiError> {
+    let Some(version) = headers.get("key-version") else {
+        return Ok(None);
+    };
+    match version.to_str().ok().and_then(|x| x.parse().ok()) {
+        Some(version) => Ok(Some(version)),
+        None => Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some("Key version header must be a number.".to_owned()),
+            }),
+        )),
+    }
+}
+
+fn checksum_mismatch() -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::BAD_REQUEST,
+        Json(ErrorInfo {
+            error_code: ErrorCode::ChecksumMismatch,
+            error_message: Some("Uploaded data did not match the supplied checksum.".to_owned()),
+        }),
+    )
+}
+
+fn upload_too_large(max_size: i64) -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(ErrorInfo {
+            error_code: ErrorCode::TooLarge,
+            error_message: Some(format!(
+                "Upload exceeded the maximum size of {} bytes or your storage quota.",
+                max_size.max(0)
+            )),
+        }),
+    )
+}
+
+struct UploadedObject {
+    size: i64,
+    sha256: Vec<u8>,
+}
+
+// the upload is rejected as soon as more than max_size bytes are read
+// or once the stream ends if its hash dosent match expected_checksum
 async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
     client: &Client,
     bucket: &str,
     key: &str,
     stream: &mut S,
-) -> Result<(), ApiError> {
+    max_size: i64,
+    expected_checksum: Option<&[u8]>,
+    content_type: &str,
+) -> Result<UploadedObject, ApiError> {
     // 10MB
     const CHUNK_SIZE: usize = 10 * 1024 * 1024;
     const MAX_PUT_SIZE: usize = CHUNK_SIZE * 2;
@@ -534,6 +1574,9 @@ async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
             break;
         }
         total_read_size += read_size;
+        if total_read_size as i64 > max_size {
+            return Err(upload_too_large(max_size));
+        }
 
         if total_read_size == MAX_PUT_SIZE {
             break;
@@ -541,21 +1584,34 @@ async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
     }
     first_chunk.resize(total_read_size, 0);
 
+    let mut hasher = Sha256::new();
+    hasher.update(&first_chunk);
+
     if first_chunk.len() < MAX_PUT_SIZE {
+        let uploaded_size = first_chunk.len() as i64;
+        let sha256 = hasher.finalize().to_vec();
+        if expected_checksum.is_some_and(|x| x != sha256) {
+            return Err(checksum_mismatch());
+        }
         client
             .put_object()
             .bucket(bucket)
             .key(key)
+            .content_type(content_type)
             .body(ByteStream::from(first_chunk))
             .send()
             .await?;
-        return Ok(());
+        return Ok(UploadedObject {
+            size: uploaded_size,
+            sha256,
+        });
     }
 
     let multipart_upload = client
         .create_multipart_upload()
         .bucket(bucket)
         .key(key)
+        .content_type(content_type)
         .send()
         .await?;
     let upload_id = multipart_upload
@@ -563,6 +1619,7 @@ async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
         .ok_or(ApiError::WithCode(StatusCode::INTERNAL_SERVER_ERROR))?;
 
     let mut parts: Vec<aws_sdk_s3::types::CompletedPart> = vec![];
+    let mut uploaded_size = first_chunk.len() as i64;
 
     for chunk in first_chunk.chunks_exact(CHUNK_SIZE) {
         let part_number = parts.len() as i32 + 1;
@@ -595,6 +1652,16 @@ async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
                 break;
             }
             total_read_size += read_size;
+            if uploaded_size + total_read_size as i64 > max_size {
+                client
+                    .abort_multipart_upload()
+                    .bucket(bucket)
+                    .key(key)
+                    .upload_id(&upload_id)
+                    .send()
+                    .await?;
+                return Err(upload_too_large(max_size));
+            }
             debug_assert!(total_read_size <= MAX_PUT_SIZE);
             if total_read_size == MAX_PUT_SIZE {
                 break;
@@ -605,6 +1672,8 @@ async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
         if chunk.is_empty() {
             break;
         }
+        uploaded_size += chunk.len() as i64;
+        hasher.update(&chunk);
 
         let part_number = parts.len() as i32 + 1;
 
@@ -627,6 +1696,18 @@ async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
         parts.push(part);
     }
 
+    let sha256 = hasher.finalize().to_vec();
+    if expected_checksum.is_some_and(|x| x != sha256) {
+        client
+            .abort_multipart_upload()
+            .bucket(bucket)
+            .key(key)
+            .upload_id(&upload_id)
+            .send()
+            .await?;
+        return Err(checksum_mismatch());
+    }
+
     let completed_multipart_upload = aws_sdk_s3::types::CompletedMultipartUpload::builder()
         .set_parts(Some(parts))
         .build();
@@ -650,15 +1731,19 @@ async fn upload_object_stream<S: AsyncRead + Unpin + Send>(
         return Err(ApiError::WithCode(StatusCode::INTERNAL_SERVER_ERROR));
     }
 
-    Ok(())
+    Ok(UploadedObject {
+        size: uploaded_size,
+        sha256,
+    })
 }
 
 pub fn objects_router(app_state: Arc<AppState>) -> Router {
     Router::new()
         .route(
             OBJECT_INFO_ROUTE,
-            get(get_object_info).post(create_or_update_object),
+            get(get_object_info).put(replace_object).patch(patch_object),
         )
+        .route(OBJECT_CREATE_ROUTE, post(create_object))
         .route(
             OBJECT_DOWNLOAD_ROUTE,
             get(get_object_file).post(change_object_file),
@@ -667,9 +1752,38 @@ pub fn objects_router(app_state: Arc<AppState>) -> Router {
             OBJECT_IMAGE_ROUTE,
             get(get_object_image).post(change_object_image),
         )
+        .route(
+            OBJECT_KEY_ROUTE,
+            get(get_object_key).post(rotate_object_key),
+        )
         .layer(middleware::from_fn_with_state(
             app_state.clone(),
             check_auth,
         ))
         .with_state(app_state.clone())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn private_objects_are_only_visible_to_privileged_users() {
+        let private = Publicity::Private as i16;
+        for verified in [false, true] {
+            for hide_unverified in [false, true] {
+                assert!(!is_visible(private, verified, hide_unverified, false));
+                assert!(is_visible(private, verified, hide_unverified, true));
+            }
+        }
+    }
+
+    #[test]
+    fn public_objects_need_verifying_only_if_hidden() {
+        let public = Publicity::Public as i16;
+        assert!(is_visible(public, true, true, false));
+        assert!(is_visible(public, false, false, false));
+        assert!(!is_visible(public, false, true, false));
+        assert!(is_visible(public, false, true, true));
+    }
+}
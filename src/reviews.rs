@@ -0,0 +1,475 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::ErrorCode;
+use crate::ErrorInfo;
+use crate::auth;
+use crate::collaborators::has_role;
+use crate::models;
+use crate::models::*;
+use crate::objects::find_visible_object;
+use crate::objects::to_secs;
+use crate::schema::objects;
+use crate::schema::review_reports;
+use crate::schema::reviews;
+use crate::schema::users;
+use crate::search::Cursor;
+use crate::search::Page;
+use axum::Extension;
+use axum::extract::Path;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::middleware;
+use axum::{Json, Router, routing::get, routing::post, routing::put};
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel_async::AsyncConnection;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::SystemTime;
+use uuid::Uuid;
+
+const REVIEW_ROUTE: &str = "/{object_type}/{uuid}/review";
+const REVIEWS_ROUTE: &str = "/{object_type}/{uuid}/reviews";
+const REVIEW_USER_ROUTE: &str = constcat::concat!(REVIEWS_ROUTE, "/{usr_id}");
+const REVIEW_REPLY_ROUTE: &str = constcat::concat!(REVIEW_USER_ROUTE, "/reply");
+const REVIEW_REPORT_ROUTE: &str = constcat::concat!(REVIEW_USER_ROUTE, "/report");
+
+const MAX_REVIEW_LENGTH: usize = 2048;
+const MAX_REPORT_LENGTH: usize = 1024;
+// what review cursors are tagged with so they cant be used for other listings
+const REVIEWS_ORDER: &str = "reviews";
+
+fn review_not_found() -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::NOT_FOUND,
+        Json(ErrorInfo {
+            error_code: ErrorCode::DosentExist,
+            error_message: Some("That user hasnt reviewed this object.".to_owned()),
+        }),
+    )
+}
+
+fn bad_length(what: &str) -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::BAD_REQUEST,
+        Json(ErrorInfo {
+            error_code: ErrorCode::BadRequestLength,
+            error_message: Some(format!("{} was wrong length. This shouldnt happen", what)),
+        }),
+    )
+}
+
+// removes a review and takes its rating out of the objects score
+// returns false if there was no review to remove
+pub async fn delete_review(
+    conn: &mut AsyncPgConnection,
+    object_id: Uuid,
+    usr_id: Uuid,
+) -> Result<bool, ApiError> {
+    conn.transaction(|conn| {
+        async move {
+            let Some(rating) = diesel::delete(reviews::table)
+                .filter(reviews::object.eq(object_id))
+                .filter(reviews::user.eq(usr_id))
+                .returning(reviews::rating)
+                .get_result::<i16>(conn)
+                .await
+                .optional()?
+            else {
+                return Ok(false);
+            };
+
+            diesel::update(objects::table)
+                .filter(objects::id.eq(object_id))
+                .set((
+                    objects::rating_count.eq(objects::rating_count - 1),
+                    objects::rating_sum.eq(objects::rating_sum - rating as i32),
+                ))
+                .execute(conn)
+                .await?;
+
+            Ok(true)
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+#[derive(Deserialize)]
+pub struct ReviewUpload {
+    rating: i16,
+    // a rating on its own is fine
+    #[serde(default)]
+    text: String,
+}
+
+// creates or replaces the users review, each user gets one per object
+pub async fn set_review(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<ReviewUpload>,
+) -> Result<(), ApiError> {
+    if !(1..=5).contains(&json.rating) {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some("Ratings must be between 1 and 5.".to_owned()),
+            }),
+        ));
+    }
+    if json.text.len() > MAX_REVIEW_LENGTH {
+        return Err(bad_length("Review"));
+    }
+
+    let mut conn = state.pool.get().await?;
+    let creator =
+        find_visible_object(&state, &mut conn, Some(object_type), object_id, user_id).await?;
+
+    // collaborators of any role count as the objects own team
+    if has_role(
+        &mut conn,
+        object_id,
+        creator,
+        user_id,
+        CollaboratorRole::Viewer,
+    )
+    .await?
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some(
+                    "You cannot review objects you created or collaborate on.".to_owned(),
+                ),
+            }),
+        ));
+    }
+
+    conn.transaction(|mut conn| {
+        async move {
+            let now = SystemTime::now();
+            let previous = reviews::table
+                .select(reviews::rating)
+                .filter(reviews::object.eq(object_id))
+                .filter(reviews::user.eq(user_id))
+                .for_update()
+                .first::<i16>(&mut conn)
+                .await
+                .optional()?;
+
+            match previous {
+                Some(previous) => {
+                    diesel::update(reviews::table)
+                        .filter(reviews::object.eq(object_id))
+                        .filter(reviews::user.eq(user_id))
+                        .set((
+                            reviews::rating.eq(json.rating),
+                            reviews::text.eq(&json.text),
+                            reviews::updated_at.eq(now),
+                        ))
+                        .execute(&mut conn)
+                        .await?;
+
+                    diesel::update(objects::table)
+                        .filter(objects::id.eq(object_id))
+                        .set(
+                            objects::rating_sum
+                                .eq(objects::rating_sum + (json.rating - previous) as i32),
+                        )
+                        .execute(&mut conn)
+                        .await?;
+                }
+                None => {
+                    insert_into(reviews::table)
+                        .values(Review {
+                            object: object_id,
+                            user: user_id,
+                            rating: json.rating,
+                            text: json.text,
+                            reply: None,
+                            replied_at: None,
+                            created_at: now,
+                            updated_at: now,
+                        })
+                        .execute(&mut conn)
+                        .await?;
+
+                    diesel::update(objects::table)
+                        .filter(objects::id.eq(object_id))
+                        .set((
+                            objects::rating_count.eq(objects::rating_count + 1),
+                            objects::rating_sum.eq(objects::rating_sum + json.rating as i32),
+                        ))
+                        .execute(&mut conn)
+                        .await?;
+                }
+            }
+
+            Ok(())
+        }
+        .scope_boxed()
+    })
+    .await
+}
+
+pub async fn remove_review(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    find_visible_object(&state, &mut conn, Some(object_type), object_id, user_id).await?;
+
+    if !delete_review(&mut conn, object_id, user_id).await? {
+        return Err(review_not_found());
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReviewsQuery {
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ReviewInfo {
+    pub user: Uuid,
+    pub username: String,
+    pub rating: i16,
+    pub text: String,
+    pub created_at: u64,
+    pub updated_at: u64,
+    pub reply: Option<String>,
+    pub replied_at: Option<u64>,
+}
+
+#[derive(Serialize)]
+pub struct ReviewPage {
+    pub total: i64,
+    pub reviews: Vec<ReviewInfo>,
+    // pass back as the cursor parameter to get the next page, missing on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+}
+
+// newest first, ties go by user so pages dont skip or repeat reviews
+pub async fn get_reviews(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    Query(query): Query<ReviewsQuery>,
+) -> Result<Json<ReviewPage>, ApiError> {
+    let page = Page::new(query.limit, query.cursor.as_deref(), 1)?;
+
+    let mut conn = state.pool.get().await?;
+    find_visible_object(&state, &mut conn, Some(object_type), object_id, user_id).await?;
+
+    let total = reviews::table
+        .count()
+        .filter(reviews::object.eq(object_id))
+        .get_result::<i64>(&mut conn)
+        .await?;
+
+    let mut query = reviews::table
+        .inner_join(users::table)
+        .select((Review::as_select(), users::username))
+        .filter(reviews::object.eq(object_id))
+        .order((reviews::updated_at.desc(), reviews::user.asc()))
+        .limit(page.limit + 1)
+        .into_boxed();
+    if let Some(cursor) = &page.cursor {
+        let updated_at: SystemTime = cursor.key_for(REVIEWS_ORDER)?;
+        query = query.filter(
+            reviews::updated_at.lt(updated_at).or(reviews::updated_at
+                .eq(updated_at)
+                .and(reviews::user.gt(cursor.id))),
+        );
+    }
+    let mut reviews = query.load::<(Review, String)>(&mut conn).await?;
+
+    // one extra row is loaded to tell if theres another page
+    let next_cursor = if reviews.len() as i64 > page.limit {
+        reviews.truncate(page.limit as usize);
+        reviews
+            .last()
+            .map(|(review, _)| Cursor::encode(REVIEWS_ORDER, review.user, &review.updated_at))
+    } else {
+        None
+    };
+
+    Ok(Json(ReviewPage {
+        total,
+        next_cursor,
+        reviews: reviews
+            .into_iter()
+            .map(|(review, username)| ReviewInfo {
+                user: review.user,
+                username,
+                rating: review.rating,
+                text: review.text,
+                created_at: to_secs(review.created_at),
+                updated_at: to_secs(review.updated_at),
+                reply: review.reply,
+                replied_at: review.replied_at.map(to_secs),
+            })
+            .collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ReplyRequest {
+    text: String,
+}
+
+// the creator and editors can reply on behalf of the object
+async fn update_reply(
+    state: Arc<AppState>,
+    object_type: models::ObjectType,
+    object_id: Uuid,
+    usr_id: Uuid,
+    user_id: Uuid,
+    reply: Option<String>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+    let creator =
+        find_visible_object(&state, &mut conn, Some(object_type), object_id, user_id).await?;
+
+    if !has_role(
+        &mut conn,
+        object_id,
+        creator,
+        user_id,
+        CollaboratorRole::Editor,
+    )
+    .await?
+    {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some(
+                    "Only the creator and editors can reply to reviews.".to_owned(),
+                ),
+            }),
+        ));
+    }
+
+    let replied_at = reply.as_ref().map(|_| SystemTime::now());
+    if diesel::update(reviews::table)
+        .filter(reviews::object.eq(object_id))
+        .filter(reviews::user.eq(usr_id))
+        .set((reviews::reply.eq(reply), reviews::replied_at.eq(replied_at)))
+        .execute(&mut conn)
+        .await?
+        == 0
+    {
+        return Err(review_not_found());
+    }
+
+    Ok(())
+}
+
+pub async fn set_reply(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id, usr_id)): Path<(models::ObjectType, Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<ReplyRequest>,
+) -> Result<(), ApiError> {
+    if json.text.is_empty() || json.text.len() > MAX_REVIEW_LENGTH {
+        return Err(bad_length("Reply"));
+    }
+    update_reply(
+        state,
+        object_type,
+        object_id,
+        usr_id,
+        user_id,
+        Some(json.text),
+    )
+    .await
+}
+
+pub async fn remove_reply(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id, usr_id)): Path<(models::ObjectType, Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    update_reply(state, object_type, object_id, usr_id, user_id, None).await
+}
+
+#[derive(Deserialize)]
+pub struct ReportRequest {
+    reason: String,
+}
+
+// reported reviews show up in the moderation queue, reporting twice does nothing
+pub async fn report_review(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id, usr_id)): Path<(models::ObjectType, Uuid, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+    Json(json): Json<ReportRequest>,
+) -> Result<(), ApiError> {
+    if json.reason.is_empty() || json.reason.len() > MAX_REPORT_LENGTH {
+        return Err(bad_length("Report reason"));
+    }
+    if usr_id == user_id {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InvalidRequest,
+                error_message: Some("You cant report your own review.".to_owned()),
+            }),
+        ));
+    }
+
+    let mut conn = state.pool.get().await?;
+    find_visible_object(&state, &mut conn, Some(object_type), object_id, user_id).await?;
+
+    if reviews::table
+        .count()
+        .filter(reviews::object.eq(object_id))
+        .filter(reviews::user.eq(usr_id))
+        .get_result::<i64>(&mut conn)
+        .await?
+        == 0
+    {
+        return Err(review_not_found());
+    }
+
+    insert_into(review_reports::table)
+        .values(ReviewReport {
+            object: object_id,
+            review_user: usr_id,
+            reporter: user_id,
+            reason: json.reason,
+            created_at: SystemTime::now(),
+        })
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+pub fn reviews_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(REVIEW_ROUTE, put(set_review).delete(remove_review))
+        .route(REVIEWS_ROUTE, get(get_reviews))
+        .route(REVIEW_REPLY_ROUTE, put(set_reply).delete(remove_reply))
+        .route(REVIEW_REPORT_ROUTE, post(report_review))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,399 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::models::ObjectScan;
+use crate::models::ScanStatus;
+use crate::schema::object_scans;
+use crate::schema::objects;
+use aws_sdk_s3::Client;
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+use diesel_async::RunQueryDsl;
+use std::env;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tracing::error;
+use uuid::Uuid;
+
+// epck files are godot packs, usually with an encrypted directory
+const PACK_MAGIC: &[u8; 4] = b"GDPC";
+const PACK_DIR_ENCRYPTED: u32 = 1;
+// large enough for the version 2 and 3 headers
+const PACK_HEADER_SIZE: usize = 104;
+
+const DEFAULT_MAX_PACKAGE_SIZE: i64 = 8 * 1024 * 1024 * 1024;
+const DEFAULT_MAX_PACKAGE_ENTRIES: u32 = 65536;
+
+// clamd closes the connection if a single chunk is larger than its StreamMaxLength
+const CLAMAV_CHUNK_SIZE: usize = 64 * 1024;
+
+pub enum Scanner {
+    // checks the pack header and its size and entry count
+    Header { max_size: i64, max_entries: u32 },
+    // streams the package to clamd using the INSTREAM command
+    ClamAv { address: String },
+    // passes everything, for tests and local development
+    NoOp,
+}
+
+pub enum ScanVerdict {
+    Clean,
+    Quarantined(String),
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScanError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("s3 error: {0}")]
+    S3(#[from] aws_sdk_s3::Error),
+    #[error("s3 stream error: {0}")]
+    S3Stream(#[from] aws_sdk_s3::primitives::ByteStreamError),
+    #[error("unexpected response from clamd: {0}")]
+    UnexpectedResponse(String),
+}
+
+// PACKAGE_SCANNERS is a comma separated list of header, clamav and none
+// scanners run in order and the first one to quarantine a package stops the rest
+pub fn scanners_from_env() -> Vec<Scanner> {
+    let names = env::var("PACKAGE_SCANNERS").unwrap_or_else(|_| "header".to_owned());
+    names
+        .split(',')
+        .map(|name| match name.trim() {
+            "header" => Scanner::Header {
+                max_size: env::var("PACKAGE_MAX_SIZE")
+                    .map(|x| x.parse().expect("PACKAGE_MAX_SIZE must be a number"))
+                    .unwrap_or(DEFAULT_MAX_PACKAGE_SIZE),
+                max_entries: env::var("PACKAGE_MAX_ENTRIES")
+                    .map(|x| x.parse().expect("PACKAGE_MAX_ENTRIES must be a number"))
+                    .unwrap_or(DEFAULT_MAX_PACKAGE_ENTRIES),
+            },
+            "clamav" => Scanner::ClamAv {
+                address: env::var("CLAMAV_ADDRESS")
+                    .expect("CLAMAV_ADDRESS must be set to use the clamav scanner"),
+            },
+            "none" => Scanner::NoOp,
+            name => panic!("unknown package scanner {:?}", name),
+        })
+        .collect()
+}
+
+impl Scanner {
+    pub async fn scan(
+        &self,
+        client: &Client,
+        bucket: &str,
+        key: &str,
+        size: i64,
+    ) -> Result<ScanVerdict, ScanError> {
+        match self {
+            Scanner::Header {
+                max_size,
+                max_entries,
+            } => scan_header(client, bucket, key, size, *max_size, *max_entries).await,
+            Scanner::ClamAv { address } => {
+                let object = client
+                    .get_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(aws_sdk_s3::Error::from)?;
+                scan_clamav(address, &mut object.body.into_async_read()).await
+            }
+            Scanner::NoOp => Ok(ScanVerdict::Clean),
+        }
+    }
+}
+
+async fn read_range(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    start: u64,
+    length: usize,
+) -> Result<Vec<u8>, ScanError> {
+    let object = client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .range(format!("bytes={}-{}", start, start + length as u64 - 1))
+        .send()
+        .await
+        .map_err(aws_sdk_s3::Error::from)?;
+    Ok(object.body.collect().await?.to_vec())
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+async fn scan_header(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    size: i64,
+    max_size: i64,
+    max_entries: u32,
+) -> Result<ScanVerdict, ScanError> {
+    if size > max_size {
+        return Ok(ScanVerdict::Quarantined(format!(
+            "Package is larger than {} bytes.",
+            max_size
+        )));
+    }
+    if size < PACK_HEADER_SIZE as i64 {
+        return Ok(ScanVerdict::Quarantined(
+            "Package is too small to be valid.".to_owned(),
+        ));
+    }
+
+    let header = read_range(client, bucket, key, 0, PACK_HEADER_SIZE).await?;
+    let entry_count = match parse_header(&header, size) {
+        Ok(PackDirectory::Encrypted) => return Ok(ScanVerdict::Clean),
+        Ok(PackDirectory::Entries(entry_count)) => entry_count,
+        Ok(PackDirectory::At(dir_offset)) => {
+            read_u32(&read_range(client, bucket, key, dir_offset, 4).await?, 0)
+        }
+        Err(reason) => return Ok(ScanVerdict::Quarantined(reason)),
+    };
+
+    if entry_count > max_entries {
+        return Ok(ScanVerdict::Quarantined(format!(
+            "Package has {} entries, the limit is {}.",
+            entry_count, max_entries
+        )));
+    }
+    Ok(ScanVerdict::Clean)
+}
+
+// where the entry count of a pack is, as far as the header can tell
+#[derive(Debug, PartialEq)]
+enum PackDirectory {
+    // the directory is encrypted along with its entry count so theres nothing more to check
+    Encrypted,
+    Entries(u32),
+    // the entry count is the first thing in the directory at this offset
+    At(u64),
+}
+
+// errors are the reason the package should be quarantined
+fn parse_header(header: &[u8], size: i64) -> Result<PackDirectory, String> {
+    if header.len() < PACK_HEADER_SIZE || &header[0..4] != PACK_MAGIC {
+        return Err("Package is not a godot pack.".to_owned());
+    }
+
+    // magic, format version, engine major/minor/patch, flags, file base
+    let version = read_u32(header, 4);
+    let flags = read_u32(header, 20);
+    match version {
+        2 | 3 if flags & PACK_DIR_ENCRYPTED != 0 => Ok(PackDirectory::Encrypted),
+        // followed by 16 reserved u32s then the entry count
+        2 => Ok(PackDirectory::Entries(read_u32(header, 96))),
+        // followed by the directory offset
+        3 => {
+            let dir_offset = u64::from_le_bytes(header[32..40].try_into().unwrap());
+            if dir_offset.saturating_add(4) > size as u64 {
+                return Err("Package directory is out of bounds.".to_owned());
+            }
+            Ok(PackDirectory::At(dir_offset))
+        }
+        version => Err(format!("Unsupported pack format version {}.", version)),
+    }
+}
+
+async fn scan_clamav<S: AsyncRead + Unpin>(
+    address: &str,
+    stream: &mut S,
+) -> Result<ScanVerdict, ScanError> {
+    let mut socket = TcpStream::connect(address).await?;
+    socket.write_all(b"zINSTREAM\0").await?;
+
+    // each chunk is prefixed by its length, a zero length chunk ends the stream
+    let mut chunk = vec![0u8; CLAMAV_CHUNK_SIZE];
+    loop {
+        let read_size = stream.read(&mut chunk).await?;
+        if read_size == 0 {
+            break;
+        }
+        socket.write_all(&(read_size as u32).to_be_bytes()).await?;
+        socket.write_all(&chunk[..read_size]).await?;
+    }
+    socket.write_all(&0u32.to_be_bytes()).await?;
+
+    let mut response = Vec::new();
+    socket.read_to_end(&mut response).await?;
+    let response = String::from_utf8_lossy(&response);
+    let response = response.trim_end_matches('\0').trim();
+
+    // responses look like "stream: OK" or "stream: Some.Signature FOUND"
+    if response.ends_with("OK") {
+        Ok(ScanVerdict::Clean)
+    } else if let Some(signature) = response
+        .strip_prefix("stream: ")
+        .and_then(|x| x.strip_suffix(" FOUND"))
+    {
+        Ok(ScanVerdict::Quarantined(format!(
+            "Package matched malware signature {}.",
+            signature
+        )))
+    } else {
+        Err(ScanError::UnexpectedResponse(response.to_owned()))
+    }
+}
+
+// runs every configured scanner over a freshly uploaded package and records the result
+// the object is only updated if it hasnt been replaced by another upload in the meantime
+pub async fn scan_package(
+    state: Arc<AppState>,
+    object_id: Uuid,
+    bucket: &'static str,
+    object_sha256: Vec<u8>,
+    size: i64,
+) {
+    let key = object_id.to_string();
+    let mut status = ScanStatus::Clean;
+    let mut details = None;
+    for scanner in state.package_scanners.iter() {
+        match scanner.scan(&state.s3_client, bucket, &key, size).await {
+            Ok(ScanVerdict::Clean) => {}
+            Ok(ScanVerdict::Quarantined(reason)) => {
+                status = ScanStatus::Quarantined;
+                details = Some(reason);
+                break;
+            }
+            Err(err) => {
+                error!("failed to scan package {}: {:?}", object_id, err);
+                status = ScanStatus::Failed;
+                details = Some("Package could not be scanned, try uploading it again.".to_owned());
+                break;
+            }
+        }
+    }
+
+    // errors are logged when they get converted into an ApiError
+    let _ = record_scan(&state, object_id, object_sha256, status, details).await;
+}
+
+async fn record_scan(
+    state: &AppState,
+    object_id: Uuid,
+    object_sha256: Vec<u8>,
+    status: ScanStatus,
+    details: Option<String>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    insert_into(object_scans::table)
+        .values(ObjectScan {
+            object: object_id,
+            object_sha256: object_sha256.clone(),
+            scanned_at: SystemTime::now(),
+            status: status as i16,
+            details: details.clone(),
+        })
+        .execute(&mut conn)
+        .await?;
+
+    // quarantine reasons are shown to the creator the same way a rejection is
+    // and a quarantine takes back any approval the package already had
+    diesel::update(objects::table)
+        .filter(objects::id.eq(object_id))
+        .filter(objects::object_sha256.eq(&object_sha256))
+        .set((
+            objects::scan_status.eq(status as i16),
+            objects::verified
+                .eq(objects::verified.and::<_, Bool>(status != ScanStatus::Quarantined)),
+            rejection_reason(status, details).map(|x| objects::rejection_reason.eq(x)),
+        ))
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+// only a failed scan replaces the reason, a clean scan has nothing to say
+// and shouldnt erase a rejection a moderator already gave
+fn rejection_reason(status: ScanStatus, details: Option<String>) -> Option<Option<String>> {
+    match status {
+        ScanStatus::Quarantined | ScanStatus::Failed => Some(details),
+        ScanStatus::Pending | ScanStatus::Clean => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version: u32, flags: u32) -> Vec<u8> {
+        let mut header = vec![0u8; PACK_HEADER_SIZE];
+        header[0..4].copy_from_slice(PACK_MAGIC);
+        header[4..8].copy_from_slice(&version.to_le_bytes());
+        header[20..24].copy_from_slice(&flags.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn reads_version_2_entry_count() {
+        let mut header = header(2, 0);
+        header[96..100].copy_from_slice(&1234u32.to_le_bytes());
+        assert_eq!(
+            parse_header(&header, 4096),
+            Ok(PackDirectory::Entries(1234))
+        );
+    }
+
+    #[test]
+    fn finds_version_3_directory() {
+        let mut header = header(3, 0);
+        header[32..40].copy_from_slice(&1000u64.to_le_bytes());
+        assert_eq!(parse_header(&header, 1004), Ok(PackDirectory::At(1000)));
+        // the entry count has to fit in the package too
+        assert!(parse_header(&header, 1003).is_err());
+
+        header[32..40].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(parse_header(&header, 4096).is_err());
+    }
+
+    #[test]
+    fn skips_encrypted_directories() {
+        for version in [2, 3] {
+            assert_eq!(
+                parse_header(&header(version, PACK_DIR_ENCRYPTED), 4096),
+                Ok(PackDirectory::Encrypted)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let mut not_a_pack = header(2, 0);
+        not_a_pack[0..4].copy_from_slice(b"PK\x03\x04");
+        assert!(parse_header(&not_a_pack, 4096).is_err());
+        assert!(parse_header(&header(2, 0)[..PACK_HEADER_SIZE - 1], 4096).is_err());
+        assert_eq!(
+            parse_header(&header(1, 0), 4096),
+            Err("Unsupported pack format version 1.".to_owned())
+        );
+    }
+
+    #[test]
+    fn clean_rescan_keeps_rejection() {
+        assert_eq!(rejection_reason(ScanStatus::Clean, None), None);
+        assert_eq!(rejection_reason(ScanStatus::Pending, None), None);
+    }
+
+    #[test]
+    fn failed_scan_sets_rejection() {
+        let details = Some("Eicar-Signature".to_owned());
+        assert_eq!(
+            rejection_reason(ScanStatus::Quarantined, details.clone()),
+            Some(details)
+        );
+        assert_eq!(rejection_reason(ScanStatus::Failed, None), Some(None));
+    }
+}
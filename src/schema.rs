@@ -1,21 +1,92 @@
 // @generated automatically by Diesel CLI.
 
+pub mod sql_types {
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "tsvector", schema = "pg_catalog"))]
+    pub struct Tsvector;
+}
+
+diesel::table! {
+    collaborator_invites (object, user) {
+        object -> Uuid,
+        user -> Uuid,
+        role -> Int2,
+        invited_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    collection_items (collection, object) {
+        collection -> Uuid,
+        object -> Uuid,
+        position -> Int4,
+        added_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    collections (id) {
+        id -> Uuid,
+        owner -> Uuid,
+        #[max_length = 64]
+        name -> Varchar,
+        #[max_length = 1024]
+        description -> Varchar,
+        public -> Bool,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    favourites (user, object) {
+        user -> Uuid,
+        object -> Uuid,
+        added_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    featured_objects (object) {
+        object -> Uuid,
+        position -> Int4,
+        starts_at -> Nullable<Timestamp>,
+        ends_at -> Nullable<Timestamp>,
+        added_by -> Nullable<Uuid>,
+        created_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     licenses (license) {
         license -> Int4,
         #[max_length = 100000]
         text -> Varchar,
+        #[max_length = 64]
+        spdx_id -> Nullable<Varchar>,
+        #[max_length = 128]
+        name -> Varchar,
+        #[max_length = 256]
+        url -> Nullable<Varchar>,
+        custom -> Bool,
+        allows_remix -> Bool,
+        allows_commercial -> Bool,
+        requires_attribution -> Bool,
+        share_alike -> Bool,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::Tsvector;
+
     objects (id) {
         id -> Uuid,
         #[max_length = 32]
         name -> Varchar,
         #[max_length = 4096]
         description -> Varchar,
-        flags -> Array<Nullable<Bool>>,
+        flags -> Int4,
         updated_at -> Timestamp,
         created_at -> Timestamp,
         verified -> Bool,
@@ -25,8 +96,98 @@ diesel::table! {
         object_type -> Int2,
         publicity -> Int2,
         license -> Int4,
-        encryption_key -> Bytea,
-        encryption_iv -> Bytea,
+        object_sha256 -> Bytea,
+        image_sha256 -> Bytea,
+        #[max_length = 1024]
+        rejection_reason -> Nullable<Varchar>,
+        reviewed_by -> Nullable<Uuid>,
+        reviewed_at -> Nullable<Timestamp>,
+        scan_status -> Int2,
+        key_version -> Int4,
+        favourite_count -> Int4,
+        rating_count -> Int4,
+        rating_sum -> Int4,
+        weekly_uses -> Int4,
+        search_vector -> Tsvector,
+        object_uploaded_at -> Timestamp,
+        image_uploaded_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    object_collaborators (object, user) {
+        object -> Uuid,
+        user -> Uuid,
+        role -> Int2,
+        added_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    object_keys (object, version) {
+        object -> Uuid,
+        version -> Int4,
+        wrapped_key -> Bytea,
+        iv -> Bytea,
+        wrapped -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    object_scans (id) {
+        id -> Int4,
+        object -> Uuid,
+        object_sha256 -> Bytea,
+        scanned_at -> Timestamp,
+        status -> Int2,
+        #[max_length = 1024]
+        details -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    object_uses (object, user, day) {
+        object -> Uuid,
+        user -> Uuid,
+        day -> Int4,
+        kind -> Int2,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    ownership_transfers (object) {
+        object -> Uuid,
+        from_user -> Uuid,
+        to_user -> Uuid,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    review_reports (object, review_user, reporter) {
+        object -> Uuid,
+        review_user -> Uuid,
+        reporter -> Uuid,
+        #[max_length = 1024]
+        reason -> Varchar,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    reviews (object, user) {
+        object -> Uuid,
+        user -> Uuid,
+        rating -> Int2,
+        #[max_length = 2048]
+        text -> Varchar,
+        #[max_length = 2048]
+        reply -> Nullable<Varchar>,
+        replied_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -77,13 +238,43 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(collaborator_invites -> objects (object));
+diesel::joinable!(collaborator_invites -> users (user));
+diesel::joinable!(collection_items -> collections (collection));
+diesel::joinable!(collection_items -> objects (object));
+diesel::joinable!(collections -> users (owner));
+diesel::joinable!(favourites -> objects (object));
+diesel::joinable!(favourites -> users (user));
+diesel::joinable!(featured_objects -> objects (object));
+diesel::joinable!(object_collaborators -> objects (object));
+diesel::joinable!(object_collaborators -> users (user));
+diesel::joinable!(object_keys -> objects (object));
+diesel::joinable!(object_scans -> objects (object));
+diesel::joinable!(object_uses -> objects (object));
+diesel::joinable!(object_uses -> users (user));
 diesel::joinable!(objects -> licenses (license));
+diesel::joinable!(ownership_transfers -> objects (object));
+diesel::joinable!(review_reports -> users (reporter));
+diesel::joinable!(reviews -> objects (object));
+diesel::joinable!(reviews -> users (user));
 diesel::joinable!(tags -> objects (object));
 diesel::joinable!(tokens -> users (user));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    collaborator_invites,
+    collection_items,
+    collections,
+    favourites,
+    featured_objects,
     licenses,
+    object_collaborators,
+    object_keys,
+    object_scans,
+    object_uses,
     objects,
+    ownership_transfers,
+    review_reports,
+    reviews,
     tags,
     tokens,
     unverified_users,
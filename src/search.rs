@@ -1,69 +1,532 @@
 use crate::ApiError;
 use crate::AppState;
+use crate::ErrorCode;
+use crate::ErrorInfo;
 use crate::auth;
+use crate::licenses::LicenseInfo;
 use crate::models::Object;
+use crate::models::ObjectFlag;
+use crate::models::ObjectType;
 use crate::models::PublicUserInfo;
+use crate::models::Publicity;
+use crate::models::ScanStatus;
+use crate::objects::ObjectSummary;
+use crate::objects::load_tags;
+use crate::schema::licenses;
+use crate::schema::object_collaborators;
 use crate::schema::objects;
 use crate::schema::tags;
 use crate::schema::users;
+use crate::tags::normalize_tag;
+use axum::Extension;
 use axum::extract::Path;
+use axum::extract::RawQuery;
 use axum::extract::State;
+use axum::http::HeaderValue;
 use axum::http::StatusCode;
 use axum::middleware;
+use axum::response::IntoResponse;
+use axum::response::Response;
 use axum::{Json, Router, routing::get};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as BASE64;
+use diesel::dsl::sql;
 use diesel::prelude::*;
+use diesel::sql_types::BigInt;
+use diesel::sql_types::Bool;
+use diesel::sql_types::Float;
+use diesel::sql_types::Integer;
+use diesel::sql_types::Text;
 use diesel_async::AsyncPgConnection;
 use diesel_async::RunQueryDsl;
+use futures_util::future::try_join_all;
+use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
+use sha2::Digest;
+use sha2::Sha256;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 use uuid::Uuid;
 
-const SEARCH_ROUTE: &str = "/search/{query}";
+const SEARCH_ROUTE: &str = "/search";
+// deprecated, the term and filters are packed into the path like "term&is:world,sort:name"
+const LEGACY_SEARCH_ROUTE: &str = constcat::concat!(SEARCH_ROUTE, "/{query}");
+
+const MAX_TERM_LENGTH: usize = 256;
+const DEFAULT_LIMIT: i64 = 50;
+// used for each section when searching more than one type at once
+const DEFAULT_SECTION_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 100;
+// the legacy route cant page, so it keeps returning as much as it always did
+const LEGACY_LIMIT: i64 = 500;
 
 #[derive(Serialize)]
 pub struct SearchResult {
+    // keyed by section, e.g. users or worlds, sections on their last page are missing
+    // pass one back as the cursor parameter along with that sections type to get its next page
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    next_cursors: BTreeMap<&'static str, String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     users: Option<Vec<PublicUserInfo>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    worlds: Option<Vec<Object>>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    avatars: Option<Vec<Object>>,
+    // keyed by the search field of each object type, e.g. worlds
+    #[serde(flatten)]
+    objects: BTreeMap<&'static str, Vec<ObjectSummary>>,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
 pub enum FilterObjectTypes {
-    World = 0,
-    Avatar = 1,
-    User = 2,
+    Object(ObjectType),
+    User,
+}
+
+impl TryFrom<String> for FilterObjectTypes {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        if name == "user" {
+            return Ok(FilterObjectTypes::User);
+        }
+        ObjectType::from_name(&name)
+            .map(FilterObjectTypes::Object)
+            .ok_or_else(|| format!("unknown type {:?}", name))
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SortTypes {
     Name,
     CreatedAt,
     UpdatedAt,
     WeeklyUses,
+    Favourites,
+}
+
+impl SortTypes {
+    pub const ALL: [SortTypes; 5] = [
+        SortTypes::Name,
+        SortTypes::CreatedAt,
+        SortTypes::UpdatedAt,
+        SortTypes::WeeklyUses,
+        SortTypes::Favourites,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortTypes::Name => "name",
+            SortTypes::CreatedAt => "created_at",
+            SortTypes::UpdatedAt => "updated_at",
+            SortTypes::WeeklyUses => "weekly_uses",
+            SortTypes::Favourites => "favourites",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<SortTypes> {
+        SortTypes::ALL.into_iter().find(|x| x.name() == name)
+    }
+
+    // names go a to z, everything else goes newest or biggest first
+    fn descending_by_default(self) -> bool {
+        self != SortTypes::Name
+    }
+
+    // users dont have dates, favourites and weekly uses are totals across their objects
+    fn supported_for_users(self) -> bool {
+        !matches!(self, SortTypes::CreatedAt | SortTypes::UpdatedAt)
+    }
+}
+
+// "updated_at", "updated_at:asc" or "updated_at:desc"
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Sort {
+    pub by: SortTypes,
+    pub descending: bool,
+}
+
+impl TryFrom<String> for Sort {
+    type Error = String;
+
+    fn try_from(sort: String) -> Result<Self, Self::Error> {
+        let (by, direction) = match sort.split_once(':') {
+            Some((by, direction)) => (by, Some(direction)),
+            None => (sort.as_str(), None),
+        };
+        let by = SortTypes::from_name(by).ok_or_else(|| {
+            format!(
+                "unknown sort {:?}, expected one of {}",
+                by,
+                SortTypes::ALL.map(SortTypes::name).join(", ")
+            )
+        })?;
+        let descending = match direction {
+            None => by.descending_by_default(),
+            Some("asc") => false,
+            Some("desc") => true,
+            Some(direction) => {
+                return Err(format!(
+                    "unknown sort direction {:?}, expected asc or desc",
+                    direction
+                ));
+            }
+        };
+        Ok(Sort { by, descending })
+    }
+}
+
+impl Sort {
+    fn name(self) -> String {
+        format!(
+            "{}_{}",
+            self.by.name(),
+            if self.descending { "desc" } else { "asc" }
+        )
+    }
+}
+
+// what results are actually ordered by, relevance is used when theres a term and no sort
+#[derive(Clone, Copy)]
+enum ObjectOrder {
+    // ranks depend on the term so it has a hash of it, see relevance_hash
+    Relevance(u64),
+    Sort(Sort),
+}
+
+impl ObjectOrder {
+    fn name(self) -> String {
+        match self {
+            ObjectOrder::Relevance(term_hash) => format!("relevance_{:016x}", term_hash),
+            ObjectOrder::Sort(sort) => sort.name(),
+        }
+    }
+}
+
+// relevance cursors are only valid for the term they were made with
+fn relevance_hash(term: &str) -> u64 {
+    u64::from_be_bytes(Sha256::digest(term.as_bytes())[..8].try_into().unwrap())
+}
+
+// values results can be ordered by, as theyre written in cursors
+pub(crate) trait CursorKey: Sized {
+    fn to_key(&self) -> String;
+    fn from_key(key: &str) -> Option<Self>;
+}
+
+macro_rules! cursor_key_from_str {
+    ($($t:ty),*) => {$(
+        impl CursorKey for $t {
+            fn to_key(&self) -> String {
+                self.to_string()
+            }
+
+            fn from_key(key: &str) -> Option<Self> {
+                key.parse().ok()
+            }
+        }
+    )*};
+}
+
+cursor_key_from_str!(String, i32, i64, f32, Uuid);
+
+// timestamps are in microseconds, the same precision postgres stores
+impl CursorKey for SystemTime {
+    fn to_key(&self) -> String {
+        (self.duration_since(UNIX_EPOCH).unwrap().as_micros() as i64).to_string()
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        let micros: u64 = key.parse().ok()?;
+        Some(UNIX_EPOCH + Duration::from_micros(micros))
+    }
+}
+
+// for orders that need a second tie breaker, the first key cant contain a comma
+impl<A: CursorKey, B: CursorKey> CursorKey for (A, B) {
+    fn to_key(&self) -> String {
+        format!("{},{}", self.0.to_key(), self.1.to_key())
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        let (a, b) = key.split_once(',')?;
+        Some((A::from_key(a)?, B::from_key(b)?))
+    }
+}
+
+// orders a boxed query by a column and then id, skipping everything up to the cursor
+macro_rules! order_by_column {
+    ($query:ident, $column:expr, $id:expr, $sort:expr, $cursor:expr, $key:ty) => {
+        if $sort.descending {
+            $query = $query.order(($column.desc(), $id.asc()));
+        } else {
+            $query = $query.order(($column.asc(), $id.asc()));
+        }
+        if let Some(cursor) = $cursor {
+            let key: $key = cursor.key_for(&$sort.name())?;
+            $query = if $sort.descending {
+                $query.filter(
+                    $column
+                        .lt(key.clone())
+                        .or($column.eq(key).and($id.gt(cursor.id))),
+                )
+            } else {
+                $query.filter(
+                    $column
+                        .gt(key.clone())
+                        .or($column.eq(key).and($id.gt(cursor.id))),
+                )
+            };
+        }
+    };
+}
+
+// where the previous page ended, base64 of "<order>:<id>:<key>"
+// key is the value the last result was sorted by and id breaks ties
+#[derive(Debug)]
+pub struct Cursor {
+    order: String,
+    pub(crate) id: Uuid,
+    key: String,
+}
+
+impl Cursor {
+    pub(crate) fn encode(order: &str, id: Uuid, key: &impl CursorKey) -> String {
+        BASE64.encode(format!("{}:{}:{}", order, id, key.to_key()))
+    }
+
+    fn decode(cursor: &str) -> Option<Cursor> {
+        let decoded = String::from_utf8(BASE64.decode(cursor).ok()?).ok()?;
+        let mut parts = decoded.splitn(3, ':');
+        Some(Cursor {
+            order: parts.next()?.to_owned(),
+            id: Uuid::parse_str(parts.next()?).ok()?,
+            key: parts.next()?.to_owned(),
+        })
+    }
+
+    // cursors only work with the order they were made for
+    pub(crate) fn key_for<T: CursorKey>(&self, order: &str) -> Result<T, ApiError> {
+        if self.order != order {
+            return Err(invalid_cursor());
+        }
+        T::from_key(&self.key).ok_or_else(invalid_cursor)
+    }
+}
+
+fn invalid_cursor() -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::BAD_REQUEST,
+        Json(ErrorInfo {
+            error_code: ErrorCode::InvalidRequest,
+            error_message: Some(
+                "Invalid search parameter cursor: it dosent belong to this search".to_owned(),
+            ),
+        }),
+    )
+}
+
+pub struct Page {
+    pub(crate) limit: i64,
+    pub(crate) cursor: Option<Cursor>,
+}
+
+impl Page {
+    // the limit applies to each section separately
+    pub fn new(
+        limit: Option<i64>,
+        cursor: Option<&str>,
+        sections: usize,
+    ) -> Result<Page, ApiError> {
+        if cursor.is_some() && sections > 1 {
+            return Err(ApiError::WithResponse(
+                StatusCode::BAD_REQUEST,
+                Json(ErrorInfo {
+                    error_code: ErrorCode::InvalidRequest,
+                    error_message: Some(
+                        "Invalid search parameter cursor: only one type can be searched with a cursor"
+                            .to_owned(),
+                    ),
+                }),
+            ));
+        }
+        let default_limit = if sections > 1 {
+            DEFAULT_SECTION_LIMIT
+        } else {
+            DEFAULT_LIMIT
+        };
+        Ok(Page {
+            limit: limit.unwrap_or(default_limit).clamp(1, MAX_LIMIT),
+            cursor: cursor
+                .map(|x| Cursor::decode(x).ok_or_else(invalid_cursor))
+                .transpose()?,
+        })
+    }
+}
+
+// far future dates are clamped so they cant overflow
+pub fn from_secs(secs: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs.min(i64::MAX as u64 / 1_000_000))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LicenseKind {
+    // from the catalogue
+    Standard,
+    Custom,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Filter {
     Invalid,
     Is(FilterObjectTypes),
     Creator(Uuid),
-    SortBy(SortTypes),
+    SortBy(Sort),
+    // spdx id of a standard license
+    License(String),
+    AllowsRemix(bool),
+    AllowsCommercial(bool),
+    // whether objects must or must not have the flag
+    Flag(ObjectFlag, bool),
+    // objects must have every tag filter
+    Tag(String),
+    // objects must have at least one of these
+    AnyTag(Vec<String>),
+    // objects cant have any excluded tag
+    ExcludeTag(String),
+    // after is inclusive, before is exclusive
+    CreatedAfter(SystemTime),
+    CreatedBefore(SystemTime),
+    UpdatedAfter(SystemTime),
+    UpdatedBefore(SystemTime),
+    // in bytes, for anyone who cant download large packages
+    MaxSize(i64),
+    LicenseKind(LicenseKind),
+    Verified(bool),
+}
+
+// GET /search?q=...&type=world&creator=...&sort=...&tag=...
+// type, flag, noflag, tag, anytag and notag can be repeated, every type is searched if there isnt one
+// results have every tag, at least one anytag and no notag
+// dates are in seconds since the unix epoch
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SearchParams {
+    #[serde(default)]
+    q: String,
+    #[serde(default, rename = "type")]
+    types: Vec<FilterObjectTypes>,
+    creator: Option<Uuid>,
+    sort: Option<Sort>,
+    #[serde(default, rename = "tag")]
+    tags: Vec<String>,
+    #[serde(default, rename = "anytag")]
+    any_tags: Vec<String>,
+    #[serde(default, rename = "notag")]
+    excluded_tags: Vec<String>,
+    license: Option<String>,
+    remix: Option<bool>,
+    commercial: Option<bool>,
+    #[serde(default, rename = "flag")]
+    flags: Vec<ObjectFlag>,
+    #[serde(default, rename = "noflag")]
+    noflags: Vec<ObjectFlag>,
+    created_after: Option<u64>,
+    created_before: Option<u64>,
+    updated_after: Option<u64>,
+    updated_before: Option<u64>,
+    max_size: Option<u64>,
+    license_kind: Option<LicenseKind>,
+    verified: Option<bool>,
+    // at most MAX_LIMIT
+    limit: Option<i64>,
+    cursor: Option<String>,
+}
+
+impl SearchParams {
+    fn into_filters(self) -> (String, Vec<Filter>) {
+        let mut filters = Vec::new();
+        filters.extend(self.types.into_iter().map(Filter::Is));
+        filters.extend(self.creator.map(Filter::Creator));
+        filters.extend(self.sort.map(Filter::SortBy));
+        filters.extend(self.tags.iter().map(|x| Filter::Tag(normalize_tag(x))));
+        if !self.any_tags.is_empty() {
+            filters.push(Filter::AnyTag(
+                self.any_tags.iter().map(|x| normalize_tag(x)).collect(),
+            ));
+        }
+        filters.extend(
+            self.excluded_tags
+                .iter()
+                .map(|x| Filter::ExcludeTag(normalize_tag(x))),
+        );
+        filters.extend(self.license.map(Filter::License));
+        filters.extend(self.remix.map(Filter::AllowsRemix));
+        filters.extend(self.commercial.map(Filter::AllowsCommercial));
+        filters.extend(self.flags.into_iter().map(|x| Filter::Flag(x, true)));
+        filters.extend(self.noflags.into_iter().map(|x| Filter::Flag(x, false)));
+        filters.extend(
+            self.created_after
+                .map(|x| Filter::CreatedAfter(from_secs(x))),
+        );
+        filters.extend(
+            self.created_before
+                .map(|x| Filter::CreatedBefore(from_secs(x))),
+        );
+        filters.extend(
+            self.updated_after
+                .map(|x| Filter::UpdatedAfter(from_secs(x))),
+        );
+        filters.extend(
+            self.updated_before
+                .map(|x| Filter::UpdatedBefore(from_secs(x))),
+        );
+        filters.extend(
+            self.max_size
+                .map(|x| Filter::MaxSize(x.min(i64::MAX as u64) as i64)),
+        );
+        filters.extend(self.license_kind.map(Filter::LicenseKind));
+        filters.extend(self.verified.map(Filter::Verified));
+        (self.q, filters)
+    }
+}
+
+fn parse_params(
+    query: &str,
+) -> Result<SearchParams, serde_path_to_error::Error<serde_html_form::de::Error>> {
+    serde_path_to_error::deserialize(serde_html_form::Deserializer::from_bytes(query.as_bytes()))
+}
+
+fn invalid_parameter(error: serde_path_to_error::Error<serde_html_form::de::Error>) -> ApiError {
+    let parameter = error.path().to_string();
+    ApiError::WithResponse(
+        StatusCode::BAD_REQUEST,
+        Json(ErrorInfo {
+            error_code: ErrorCode::InvalidRequest,
+            // the path is just "." when the error isnt about a single parameter
+            error_message: Some(if parameter == "." {
+                format!("Invalid search: {}", error.inner())
+            } else {
+                format!("Invalid search parameter {}: {}", parameter, error.inner())
+            }),
+        }),
+    )
 }
 
-fn parse_filters(filters_map: HashMap<&str, &str>) -> Vec<Filter> {
-    let mut filters = Vec::with_capacity(filters_map.len());
+// keys can repeat, tag:a,tag:b needs both tags
+fn parse_filters(pairs: Vec<(&str, &str)>) -> Vec<Filter> {
+    let mut filters = Vec::with_capacity(pairs.len());
 
-    for filter in filters_map.into_iter() {
+    for filter in pairs.into_iter() {
         match filter {
-            ("is", type_str) => match type_str {
-                "user" => filters.push(Filter::Is(FilterObjectTypes::User)),
-                "world" => filters.push(Filter::Is(FilterObjectTypes::World)),
-                "avatar" => filters.push(Filter::Is(FilterObjectTypes::Avatar)),
-                _ => filters.push(Filter::Invalid),
+            ("is", "user") => filters.push(Filter::Is(FilterObjectTypes::User)),
+            ("is", type_str) => match ObjectType::from_name(type_str) {
+                Some(object_type) => {
+                    filters.push(Filter::Is(FilterObjectTypes::Object(object_type)))
+                }
+                None => filters.push(Filter::Invalid),
             },
 
             ("creator", id) => {
@@ -74,12 +537,34 @@ fn parse_filters(filters_map: HashMap<&str, &str>) -> Vec<Filter> {
                 }
             }
 
-            ("sort", sort) => match sort {
-                "name" => filters.push(Filter::SortBy(SortTypes::Name)),
-                "created_at" => filters.push(Filter::SortBy(SortTypes::CreatedAt)),
-                "updated_at" => filters.push(Filter::SortBy(SortTypes::UpdatedAt)),
-                "weekly_uses" => filters.push(Filter::SortBy(SortTypes::WeeklyUses)),
-                _ => filters.push(Filter::Invalid),
+            ("sort", sort) => match Sort::try_from(sort.to_owned()) {
+                Ok(sort) => filters.push(Filter::SortBy(sort)),
+                Err(_) => filters.push(Filter::Invalid),
+            },
+
+            ("license", spdx_id) => filters.push(Filter::License(spdx_id.to_owned())),
+
+            ("tag", tag) => filters.push(Filter::Tag(normalize_tag(tag))),
+            ("-tag", tag) => filters.push(Filter::ExcludeTag(normalize_tag(tag))),
+
+            ("remix", allowed) => match allowed.parse() {
+                Ok(allowed) => filters.push(Filter::AllowsRemix(allowed)),
+                Err(_) => filters.push(Filter::Invalid),
+            },
+
+            ("commercial", allowed) => match allowed.parse() {
+                Ok(allowed) => filters.push(Filter::AllowsCommercial(allowed)),
+                Err(_) => filters.push(Filter::Invalid),
+            },
+
+            ("flag", name) => match ObjectFlag::from_name(name) {
+                Some(flag) => filters.push(Filter::Flag(flag, true)),
+                None => filters.push(Filter::Invalid),
+            },
+
+            ("noflag", name) => match ObjectFlag::from_name(name) {
+                Some(flag) => filters.push(Filter::Flag(flag, false)),
+                None => filters.push(Filter::Invalid),
             },
 
             _ => filters.push(Filter::Invalid),
@@ -90,10 +575,41 @@ fn parse_filters(filters_map: HashMap<&str, &str>) -> Vec<Filter> {
 
 pub async fn search(
     State(app_state): State<Arc<AppState>>,
-    Path(query): Path<String>,
+    Extension(user_id): Extension<Uuid>,
+    RawQuery(query): RawQuery,
 ) -> Result<Json<SearchResult>, ApiError> {
-    // todo: replace unwraps with error handling
-    dbg!(&query);
+    let query = query.unwrap_or_default();
+    let params = parse_params(&query).map_err(invalid_parameter)?;
+
+    let limit = params.limit;
+    let cursor = params.cursor.clone();
+    let (term, filters) = params.into_filters();
+    let page = Page::new(limit, cursor.as_deref(), search_sections(&filters)?.len())?;
+    let term = term.trim();
+    if term.len() > MAX_TERM_LENGTH {
+        return Err(ApiError::WithResponse(
+            StatusCode::BAD_REQUEST,
+            Json(ErrorInfo {
+                error_code: ErrorCode::BadRequestLength,
+                error_message: Some(format!(
+                    "Invalid search parameter q: longer than {} bytes",
+                    MAX_TERM_LENGTH
+                )),
+            }),
+        ));
+    }
+
+    run_search(&app_state, user_id, term, &filters, &page)
+        .await
+        .map(Json)
+}
+
+// kept for older clients, use /search with query parameters instead
+pub async fn legacy_search(
+    State(app_state): State<Arc<AppState>>,
+    Path(query): Path<String>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Response, ApiError> {
     let (term, filters) = query
         .split_once('&')
         .ok_or(ApiError::WithCode(StatusCode::BAD_REQUEST))?;
@@ -101,119 +617,745 @@ pub async fn search(
     let filters = filters
         .split(',')
         .filter(|x| !x.is_empty())
-        .collect::<Vec<&str>>();
-
-    let filters: HashMap<&str, &str> = filters
-        .into_iter()
         .filter_map(|x| x.split_once(":"))
         .collect();
 
-    dbg!(&filters);
-
     let filters = parse_filters(filters);
 
-    let mut conn = app_state.pool.get().await?;
+    let page = Page {
+        limit: LEGACY_LIMIT,
+        cursor: None,
+    };
+    let mut response =
+        Json(run_search(&app_state, user_id, term.trim(), &filters, &page).await?).into_response();
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    headers.insert(
+        "link",
+        HeaderValue::from_static("</api/v0/search>; rel=\"successor-version\""),
+    );
+    Ok(response)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Users,
+    Objects(ObjectType),
+}
+
+fn unsupported_sort(sort_type: SortTypes, section: &str) -> ApiError {
+    ApiError::WithResponse(
+        StatusCode::BAD_REQUEST,
+        Json(ErrorInfo {
+            error_code: ErrorCode::InvalidRequest,
+            error_message: Some(format!(
+                "Invalid search parameter sort: {} isnt supported when searching {}",
+                sort_type.name(),
+                section
+            )),
+        }),
+    )
+}
+
+fn sort_filter(filters: &[Filter]) -> Option<Sort> {
+    filters.iter().rev().find_map(|x| match x {
+        Filter::SortBy(sort) => Some(*sort),
+        _ => None,
+    })
+}
+
+// the sections a search returns, everything if no types were asked for
+// users are left out of everything if they cant be sorted the way that was asked for
+fn search_sections(filters: &[Filter]) -> Result<Vec<Section>, ApiError> {
+    let mut sections = Vec::new();
+    for filter in filters {
+        let section = match filter {
+            Filter::Is(FilterObjectTypes::User) => Section::Users,
+            Filter::Is(FilterObjectTypes::Object(object_type)) => Section::Objects(*object_type),
+            _ => continue,
+        };
+        if !sections.contains(&section) {
+            sections.push(section);
+        }
+    }
+    let sort = sort_filter(filters);
+    if sections.is_empty() {
+        if sort.is_none_or(|x| x.by.supported_for_users()) {
+            sections.push(Section::Users);
+        }
+        sections.extend(ObjectType::ALL.map(Section::Objects));
+    } else if let Some(sort) = sort
+        && !sort.by.supported_for_users()
+        && sections.contains(&Section::Users)
+    {
+        return Err(unsupported_sort(sort.by, "users"));
+    }
+    Ok(sections)
+}
+
+// each section is searched at the same time on its own connection
+async fn run_search(
+    app_state: &AppState,
+    user_id: Uuid,
+    term: &str,
+    filters: &[Filter],
+    page: &Page,
+) -> Result<SearchResult, ApiError> {
+    let sections = search_sections(filters)?;
+
+    let users = async {
+        if !sections.contains(&Section::Users) {
+            return Ok(None);
+        }
+        let mut conn = app_state.pool.get().await?;
+        search_users(filters, term, page, &mut conn).await.map(Some)
+    };
+
+    let objects = try_join_all(sections.iter().filter_map(|section| match section {
+        Section::Objects(object_type) => Some(async move {
+            let mut conn = app_state.pool.get().await?;
+            let (objects, next_cursor) = search_objects(
+                *object_type,
+                filters,
+                term,
+                user_id,
+                app_state.hide_unverified,
+                page,
+                &mut conn,
+            )
+            .await?;
+            Ok::<_, ApiError>((object_type.info().search_field, objects, next_cursor))
+        }),
+        Section::Users => None,
+    }));
+
+    let (users, objects) = futures_util::try_join!(users, objects)?;
 
     let mut search_result = SearchResult {
+        next_cursors: BTreeMap::new(),
         users: None,
-        worlds: None,
-        avatars: None,
+        objects: BTreeMap::new(),
     };
-
-    for filter in filters.iter() {
-        match filter {
-            Filter::Is(FilterObjectTypes::User) => {
-                search_result.users = Some(search_users(&filters, term, &mut conn).await);
-                break;
-            }
-            Filter::Is(FilterObjectTypes::World) => {
-                search_result.worlds =
-                    Some(search_objects(FilterObjectTypes::World, &filters, term, &mut conn).await);
-                break;
-            }
-            Filter::Is(FilterObjectTypes::Avatar) => {
-                search_result.avatars = Some(
-                    search_objects(FilterObjectTypes::Avatar, &filters, term, &mut conn).await,
-                );
-                break;
-            }
-            _ => {}
+    if let Some((users, next_cursor)) = users {
+        search_result.users = Some(users);
+        if let Some(next_cursor) = next_cursor {
+            search_result.next_cursors.insert("users", next_cursor);
+        }
+    }
+    for (search_field, objects, next_cursor) in objects {
+        search_result.objects.insert(search_field, objects);
+        if let Some(next_cursor) = next_cursor {
+            search_result.next_cursors.insert(search_field, next_cursor);
         }
     }
 
-    Ok(Json(search_result))
+    Ok(search_result)
 }
 
+// private objects are only returned to their creator and collaborators,
+// and so are unverified ones if hide_unverified is set
+// returns a page of results and the cursor for the next one
 pub async fn search_objects(
-    object_type: FilterObjectTypes,
+    object_type: ObjectType,
     filters: &[Filter],
     search_term: &str,
+    user_id: Uuid,
+    hide_unverified: bool,
+    page: &Page,
     conn: &mut AsyncPgConnection,
-) -> Vec<Object> {
+) -> Result<(Vec<ObjectSummary>, Option<String>), ApiError> {
+    // the rank is selected so the cursor can remember it, theres nothing to rank without a term
     let mut query = objects::table
-        .select(Object::as_select())
-        .filter(objects::name.like(format!("%{}%", search_term)))
-        .or_filter(objects::description.like(format!("%{}%", search_term)))
-        .left_join(tags::table)
-        .or_filter(tags::tag.eq(search_term))
+        .inner_join(licenses::table)
         .inner_join(users::table.on(users::id.eq(objects::creator)))
-        .or_filter(users::username.like(format!("%{}%", search_term)))
+        .select((
+            Object::as_select(),
+            LicenseInfo::as_select(),
+            users::username,
+            sql::<Float>("CASE WHEN ")
+                .bind::<Text, _>(search_term.to_owned())
+                .sql(" = '' THEN 0 ELSE ts_rank(objects.search_vector, websearch_to_tsquery('simple', ")
+                .bind::<Text, _>(search_term.to_owned())
+                .sql(")) END"),
+        ))
         .filter(objects::object_type.eq(object_type as i16))
-        .limit(500)
+        .filter(objects::scan_status.ne(ScanStatus::Quarantined as i16))
+        .limit(page.limit + 1)
         .into_boxed();
 
-    dbg!(&filters);
+    let collaborating = objects::creator.eq(user_id).or(objects::id.eq_any(
+        object_collaborators::table
+            .select(object_collaborators::object)
+            .filter(object_collaborators::user.eq(user_id)),
+    ));
+    query = query.filter(
+        objects::publicity
+            .eq(Publicity::Public as i16)
+            .or(collaborating),
+    );
+    if hide_unverified {
+        query = query.filter(objects::verified.or(collaborating));
+    }
+
+    // an empty term lists everything
+    if !search_term.is_empty() {
+        query = query.filter(
+            sql::<Bool>("objects.search_vector @@ websearch_to_tsquery('simple', ")
+                .bind::<Text, _>(search_term.to_owned())
+                .sql(")"),
+        );
+    }
+
+    let mut order = if search_term.is_empty() {
+        ObjectOrder::Sort(Sort {
+            by: SortTypes::CreatedAt,
+            descending: true,
+        })
+    } else {
+        ObjectOrder::Relevance(relevance_hash(search_term))
+    };
 
     for filter in filters {
         match filter {
             Filter::Creator(owner) => {
-                query = query.filter(users::id.eq(owner));
+                query = query.filter(objects::creator.eq(owner));
             }
-            Filter::SortBy(sort_type) => match sort_type {
-                SortTypes::Name => {
-                    query = query.order(objects::name.asc());
-                }
-                SortTypes::CreatedAt => {
-                    query = query.order(objects::created_at.desc());
-                }
-                _ => {}
-            },
+            Filter::License(spdx_id) => {
+                query = query.filter(licenses::spdx_id.eq(spdx_id));
+            }
+            Filter::AllowsRemix(allowed) => {
+                query = query.filter(licenses::allows_remix.eq(allowed));
+            }
+            Filter::AllowsCommercial(allowed) => {
+                query = query.filter(licenses::allows_commercial.eq(allowed));
+            }
+            Filter::Tag(tag) => {
+                query = query.filter(
+                    objects::id.eq_any(tags::table.select(tags::object).filter(tags::tag.eq(tag))),
+                );
+            }
+            Filter::AnyTag(any_tags) => {
+                query = query.filter(
+                    objects::id.eq_any(
+                        tags::table
+                            .select(tags::object)
+                            .filter(tags::tag.eq_any(any_tags)),
+                    ),
+                );
+            }
+            Filter::ExcludeTag(tag) => {
+                query = query.filter(
+                    objects::id.ne_all(tags::table.select(tags::object).filter(tags::tag.eq(tag))),
+                );
+            }
+            Filter::Flag(flag, set) => {
+                query = query.filter(
+                    sql::<Bool>("(objects.flags & ")
+                        .bind::<Integer, _>(flag.bit())
+                        .sql(if *set { ") <> 0" } else { ") = 0" }),
+                );
+            }
+            Filter::CreatedAfter(time) => {
+                query = query.filter(objects::created_at.ge(time));
+            }
+            Filter::CreatedBefore(time) => {
+                query = query.filter(objects::created_at.lt(time));
+            }
+            Filter::UpdatedAfter(time) => {
+                query = query.filter(objects::updated_at.ge(time));
+            }
+            Filter::UpdatedBefore(time) => {
+                query = query.filter(objects::updated_at.lt(time));
+            }
+            Filter::MaxSize(size) => {
+                query = query.filter(objects::object_size.le(size));
+            }
+            Filter::LicenseKind(kind) => {
+                query = query.filter(licenses::custom.eq(*kind == LicenseKind::Custom));
+            }
+            Filter::Verified(verified) => {
+                query = query.filter(objects::verified.eq(verified));
+            }
+            Filter::SortBy(sort) => order = ObjectOrder::Sort(*sort),
             _ => {}
         }
     }
-    query.load(conn).await.unwrap()
+
+    // ties are always broken by id so every result has a unique position
+    let cursor = page.cursor.as_ref();
+    match order {
+        ObjectOrder::Relevance(_) => {
+            query = query.order((
+                sql::<Float>("ts_rank(objects.search_vector, websearch_to_tsquery('simple', ")
+                    .bind::<Text, _>(search_term.to_owned())
+                    .sql("))")
+                    .desc(),
+                objects::id.asc(),
+            ));
+            if let Some(cursor) = cursor {
+                let rank: f32 = cursor.key_for(&order.name())?;
+                query = query.filter(
+                    sql::<Bool>("(ts_rank(objects.search_vector, websearch_to_tsquery('simple', ")
+                        .bind::<Text, _>(search_term.to_owned())
+                        .sql(")) < ")
+                        .bind::<Float, _>(rank)
+                        .sql(" OR (ts_rank(objects.search_vector, websearch_to_tsquery('simple', ")
+                        .bind::<Text, _>(search_term.to_owned())
+                        .sql(")) = ")
+                        .bind::<Float, _>(rank)
+                        .sql(" AND objects.id > ")
+                        .bind::<diesel::sql_types::Uuid, _>(cursor.id)
+                        .sql("))"),
+                );
+            }
+        }
+        ObjectOrder::Sort(sort) => match sort.by {
+            SortTypes::Name => {
+                order_by_column!(query, objects::name, objects::id, sort, cursor, String);
+            }
+            SortTypes::CreatedAt => {
+                order_by_column!(
+                    query,
+                    objects::created_at,
+                    objects::id,
+                    sort,
+                    cursor,
+                    SystemTime
+                );
+            }
+            SortTypes::UpdatedAt => {
+                order_by_column!(
+                    query,
+                    objects::updated_at,
+                    objects::id,
+                    sort,
+                    cursor,
+                    SystemTime
+                );
+            }
+            SortTypes::WeeklyUses => {
+                order_by_column!(query, objects::weekly_uses, objects::id, sort, cursor, i32);
+            }
+            SortTypes::Favourites => {
+                order_by_column!(
+                    query,
+                    objects::favourite_count,
+                    objects::id,
+                    sort,
+                    cursor,
+                    i32
+                );
+            }
+        },
+    }
+
+    let mut results = query
+        .load::<(Object, LicenseInfo, String, f32)>(conn)
+        .await?;
+
+    // one extra row is loaded to tell if theres another page
+    let next_cursor = if results.len() as i64 > page.limit {
+        results.truncate(page.limit as usize);
+        results.last().map(|(object, _, _, rank)| {
+            let name = order.name();
+            match order {
+                ObjectOrder::Relevance(_) => Cursor::encode(&name, object.id, rank),
+                ObjectOrder::Sort(sort) => match sort.by {
+                    SortTypes::Name => Cursor::encode(&name, object.id, &object.name),
+                    SortTypes::CreatedAt => Cursor::encode(&name, object.id, &object.created_at),
+                    SortTypes::UpdatedAt => Cursor::encode(&name, object.id, &object.updated_at),
+                    SortTypes::WeeklyUses => Cursor::encode(&name, object.id, &object.weekly_uses),
+                    SortTypes::Favourites => {
+                        Cursor::encode(&name, object.id, &object.favourite_count)
+                    }
+                },
+            }
+        })
+    } else {
+        None
+    };
+
+    let ids: Vec<Uuid> = results.iter().map(|(object, ..)| object.id).collect();
+    let mut tags = load_tags(conn, &ids).await?;
+    Ok((
+        results
+            .into_iter()
+            .map(|(object, license, creator_username, _)| {
+                let tags = tags.remove(&object.id).unwrap_or_default();
+                ObjectSummary::new(&object, creator_username, license, tags)
+            })
+            .collect(),
+        next_cursor,
+    ))
+}
+
+// stops % and _ in user input being treated as wildcards
+pub fn escape_like(term: &str) -> String {
+    term.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// matches the term anywhere
+fn like_pattern(term: &str) -> String {
+    format!("%{}%", escape_like(term))
+}
+
+// the total of a count across everything a user has made, for sorting users
+fn user_total(sort_type: SortTypes) -> Option<&'static str> {
+    match sort_type {
+        SortTypes::WeeklyUses => Some(
+            "COALESCE((SELECT SUM(objects.weekly_uses) FROM objects WHERE objects.creator = users.id), 0)",
+        ),
+        SortTypes::Favourites => Some(
+            "COALESCE((SELECT SUM(objects.favourite_count) FROM objects WHERE objects.creator = users.id), 0)",
+        ),
+        _ => None,
+    }
 }
 
+// users are ordered by similarity to the term, or by name if theres no term
 pub async fn search_users(
     filters: &[Filter],
     search_term: &str,
+    page: &Page,
     conn: &mut AsyncPgConnection,
-) -> Vec<PublicUserInfo> {
+) -> Result<(Vec<PublicUserInfo>, Option<String>), ApiError> {
+    let sort = match sort_filter(filters) {
+        Some(sort) => Some(sort),
+        None if search_term.is_empty() => Some(Sort {
+            by: SortTypes::Name,
+            descending: false,
+        }),
+        None => None,
+    };
+    // checked before searching, this is just in case
+    if sort.is_some_and(|x| !x.by.supported_for_users()) {
+        return Err(unsupported_sort(sort.unwrap().by, "users"));
+    }
+    let total = sort.and_then(|x| user_total(x.by));
+
     let mut query = users::table
-        .select(PublicUserInfo::as_select())
-        .filter(users::username.like(format!("%{}%", search_term)))
-        .limit(100)
+        .select((
+            PublicUserInfo::as_select(),
+            sql::<Float>("similarity(users.username, ")
+                .bind::<Text, _>(search_term.to_owned())
+                .sql(")"),
+            sql::<BigInt>(total.unwrap_or("0::BIGINT")),
+        ))
+        .limit(page.limit + 1)
         .into_boxed();
-    for filter in filters {
-        match filter {
-            Filter::SortBy(sort_type) => match sort_type {
-                SortTypes::Name => {
-                    query = query.order(users::username.asc());
-                }
-                _ => {}
-            },
-            _ => {}
+
+    // trigram similarity catches typos, ilike catches terms too short to be similar
+    if !search_term.is_empty() {
+        query = query.filter(
+            sql::<Bool>("users.username % ")
+                .bind::<Text, _>(search_term.to_owned())
+                .or(users::username.ilike(like_pattern(search_term))),
+        );
+    }
+
+    let cursor = page.cursor.as_ref();
+    let order = sort.map_or("similarity".to_owned(), Sort::name);
+    match (sort, total) {
+        (Some(sort), Some(total)) => {
+            let direction = if sort.descending { "DESC" } else { "ASC" };
+            query = query.order((
+                sql::<BigInt>(&format!("{} {}", total, direction)),
+                users::id.asc(),
+            ));
+            if let Some(cursor) = cursor {
+                let key: i64 = cursor.key_for(&order)?;
+                let comparison = if sort.descending { " < " } else { " > " };
+                query = query.filter(
+                    sql::<Bool>(&format!("({}{}", total, comparison))
+                        .bind::<BigInt, _>(key)
+                        .sql(&format!(" OR ({} = ", total))
+                        .bind::<BigInt, _>(key)
+                        .sql(" AND users.id > ")
+                        .bind::<diesel::sql_types::Uuid, _>(cursor.id)
+                        .sql("))"),
+                );
+            }
+        }
+        (Some(sort), None) => {
+            order_by_column!(query, users::username, users::id, sort, cursor, String);
+        }
+        (None, _) => {
+            query = query.order((
+                sql::<Float>("similarity(users.username, ")
+                    .bind::<Text, _>(search_term.to_owned())
+                    .sql(")")
+                    .desc(),
+                users::id.asc(),
+            ));
+            if let Some(cursor) = cursor {
+                let similarity: f32 = cursor.key_for(&order)?;
+                query = query.filter(
+                    sql::<Bool>("(similarity(users.username, ")
+                        .bind::<Text, _>(search_term.to_owned())
+                        .sql(") < ")
+                        .bind::<Float, _>(similarity)
+                        .sql(" OR (similarity(users.username, ")
+                        .bind::<Text, _>(search_term.to_owned())
+                        .sql(") = ")
+                        .bind::<Float, _>(similarity)
+                        .sql(" AND users.id > ")
+                        .bind::<diesel::sql_types::Uuid, _>(cursor.id)
+                        .sql("))"),
+                );
+            }
         }
     }
-    query.load(conn).await.unwrap()
+
+    let mut results = query.load::<(PublicUserInfo, f32, i64)>(conn).await?;
+
+    let next_cursor = if results.len() as i64 > page.limit {
+        results.truncate(page.limit as usize);
+        results
+            .last()
+            .map(|(user, similarity, user_total)| match (sort, total) {
+                (Some(_), Some(_)) => Cursor::encode(&order, user.id, user_total),
+                (Some(_), None) => Cursor::encode(&order, user.id, &user.username),
+                (None, _) => Cursor::encode(&order, user.id, similarity),
+            })
+    } else {
+        None
+    };
+
+    Ok((
+        results.into_iter().map(|(user, _, _)| user).collect(),
+        next_cursor,
+    ))
 }
 
 pub fn search_router(app_state: Arc<AppState>) -> Router {
     Router::new()
         .route(SEARCH_ROUTE, get(search))
+        .route(LEGACY_SEARCH_ROUTE, get(legacy_search))
         .layer(middleware::from_fn_with_state(
             app_state.clone(),
             auth::check_auth,
         ))
         .with_state(app_state)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filters(query: &str) -> Vec<Filter> {
+        parse_params(query).unwrap().into_filters().1
+    }
+
+    fn bad_parameter(query: &str) -> String {
+        parse_params(query)
+            .err()
+            .expect("query should have been rejected")
+            .path()
+            .to_string()
+    }
+
+    #[test]
+    fn parses_term_and_types() {
+        let (term, filters) = parse_params("q=forest+cabin&type=world&type=user")
+            .unwrap()
+            .into_filters();
+        assert_eq!(term, "forest cabin");
+        assert_eq!(
+            filters,
+            vec![
+                Filter::Is(FilterObjectTypes::Object(ObjectType::World)),
+                Filter::Is(FilterObjectTypes::User),
+            ]
+        );
+        assert_eq!(bad_parameter("type=planet"), "type[0]");
+    }
+
+    #[test]
+    fn legacy_filters_keep_repeated_keys() {
+        assert_eq!(
+            parse_filters(vec![
+                ("tag", "Forest"),
+                ("tag", " cabin "),
+                ("-tag", "night"),
+                ("-tag", "horror"),
+            ]),
+            vec![
+                Filter::Tag("forest".to_owned()),
+                Filter::Tag("cabin".to_owned()),
+                Filter::ExcludeTag("night".to_owned()),
+                Filter::ExcludeTag("horror".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_date_ranges() {
+        assert_eq!(
+            filters("created_after=10&created_before=20&updated_after=30&updated_before=40"),
+            vec![
+                Filter::CreatedAfter(UNIX_EPOCH + Duration::from_secs(10)),
+                Filter::CreatedBefore(UNIX_EPOCH + Duration::from_secs(20)),
+                Filter::UpdatedAfter(UNIX_EPOCH + Duration::from_secs(30)),
+                Filter::UpdatedBefore(UNIX_EPOCH + Duration::from_secs(40)),
+            ]
+        );
+        assert_eq!(bad_parameter("created_after=yesterday"), "created_after");
+        assert_eq!(bad_parameter("updated_before=-1"), "updated_before");
+        // far future dates are clamped rather than overflowing
+        assert_eq!(
+            filters(&format!("created_before={}", u64::MAX)),
+            vec![Filter::CreatedBefore(from_secs(u64::MAX))]
+        );
+    }
+
+    #[test]
+    fn parses_max_size() {
+        assert_eq!(filters("max_size=1048576"), vec![Filter::MaxSize(1048576)]);
+        assert_eq!(
+            filters(&format!("max_size={}", u64::MAX)),
+            vec![Filter::MaxSize(i64::MAX)]
+        );
+        assert_eq!(bad_parameter("max_size=-5"), "max_size");
+        assert_eq!(bad_parameter("max_size=1GB"), "max_size");
+    }
+
+    #[test]
+    fn parses_license_filters() {
+        assert_eq!(
+            filters("license=CC-BY-4.0&remix=true&commercial=false&license_kind=custom"),
+            vec![
+                Filter::License("CC-BY-4.0".to_owned()),
+                Filter::AllowsRemix(true),
+                Filter::AllowsCommercial(false),
+                Filter::LicenseKind(LicenseKind::Custom),
+            ]
+        );
+        assert_eq!(
+            filters("license_kind=standard"),
+            vec![Filter::LicenseKind(LicenseKind::Standard)]
+        );
+        assert_eq!(bad_parameter("license_kind=free"), "license_kind");
+        assert_eq!(bad_parameter("remix=yes"), "remix");
+    }
+
+    #[test]
+    fn parses_verified() {
+        assert_eq!(filters("verified=true"), vec![Filter::Verified(true)]);
+        assert_eq!(filters("verified=false"), vec![Filter::Verified(false)]);
+        assert_eq!(bad_parameter("verified=1"), "verified");
+    }
+
+    #[test]
+    fn parses_flags() {
+        assert_eq!(
+            filters("flag=nsfw&flag=quest_compatible&noflag=contains_scripts"),
+            vec![
+                Filter::Flag(ObjectFlag::Nsfw, true),
+                Filter::Flag(ObjectFlag::QuestCompatible, true),
+                Filter::Flag(ObjectFlag::ContainsScripts, false),
+            ]
+        );
+        assert_eq!(bad_parameter("flag=nsfw&flag=shiny"), "flag[1]");
+    }
+
+    #[test]
+    fn parses_tags_normalized() {
+        assert_eq!(
+            filters("tag=+Cozy+&anytag=NIGHT&anytag=day&notag=Scary"),
+            vec![
+                Filter::Tag("cozy".to_owned()),
+                Filter::AnyTag(vec!["night".to_owned(), "day".to_owned()]),
+                Filter::ExcludeTag("scary".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_sort_directions() {
+        let sort = |by, descending| vec![Filter::SortBy(Sort { by, descending })];
+        assert_eq!(filters("sort=name"), sort(SortTypes::Name, false));
+        assert_eq!(filters("sort=updated_at"), sort(SortTypes::UpdatedAt, true));
+        assert_eq!(
+            filters("sort=updated_at:asc"),
+            sort(SortTypes::UpdatedAt, false)
+        );
+        assert_eq!(filters("sort=name:desc"), sort(SortTypes::Name, true));
+        assert_eq!(
+            filters("sort=favourites:desc"),
+            sort(SortTypes::Favourites, true)
+        );
+        assert_eq!(bad_parameter("sort=name:sideways"), "sort");
+        assert_eq!(bad_parameter("sort=:asc"), "sort");
+    }
+
+    #[test]
+    fn leaves_users_out_of_unsupported_sorts() {
+        let sections = |query| search_sections(&filters(query)).ok();
+        assert!(sections("sort=created_at").unwrap()[0] != Section::Users);
+        assert!(sections("sort=weekly_uses").unwrap()[0] == Section::Users);
+        assert!(sections("type=user&sort=updated_at:asc").is_none());
+        assert!(sections("type=world&sort=updated_at:asc").is_some());
+    }
+
+    #[test]
+    fn rejects_unknown_parameters() {
+        assert_eq!(bad_parameter("colour=red"), "colour");
+        assert_eq!(bad_parameter("creator=someone"), "creator");
+        assert_eq!(bad_parameter("sort=best"), "sort");
+    }
+
+    fn round_trip<T: CursorKey>(order: &str, key: T) -> Option<T> {
+        let id = Uuid::new_v4();
+        let cursor = Cursor::decode(&Cursor::encode(order, id, &key))?;
+        assert_eq!(cursor.id, id);
+        cursor.key_for(order).ok()
+    }
+
+    #[test]
+    fn cursor_keys_round_trip() {
+        // names can contain the separator
+        assert_eq!(
+            round_trip("name", "a:b,c".to_owned()).as_deref(),
+            Some("a:b,c")
+        );
+        assert_eq!(round_trip("weekly_uses", -7i32), Some(-7));
+        assert_eq!(round_trip("total", i64::MAX), Some(i64::MAX));
+        assert_eq!(round_trip("relevance", 0.0625f32), Some(0.0625));
+        let id = Uuid::new_v4();
+        assert_eq!(round_trip("user", id), Some(id));
+
+        let time = UNIX_EPOCH + Duration::from_micros(1_760_000_000_123_456);
+        assert_eq!(round_trip("created_at", time), Some(time));
+        assert_eq!(round_trip("reported", (time, id)), Some((time, id)));
+    }
+
+    #[test]
+    fn cursors_only_work_for_their_order() {
+        let cursor = Cursor::decode(&Cursor::encode("name_asc", Uuid::new_v4(), &5i32)).unwrap();
+        assert!(cursor.key_for::<i32>("name_desc").is_err());
+        assert!(cursor.key_for::<SystemTime>("name_asc").is_ok());
+        assert!(cursor.key_for::<(SystemTime, Uuid)>("name_asc").is_err());
+
+        assert!(Cursor::decode("not base64!").is_none());
+        assert!(Cursor::decode(&BASE64.encode("name:not-a-uuid:5")).is_none());
+    }
+
+    #[test]
+    fn relevance_cursors_belong_to_their_term() {
+        let forest = ObjectOrder::Relevance(relevance_hash("forest")).name();
+        let cabin = ObjectOrder::Relevance(relevance_hash("cabin")).name();
+        assert_ne!(forest, cabin);
+        assert_eq!(
+            forest,
+            ObjectOrder::Relevance(relevance_hash("forest")).name()
+        );
+        // the order is the part of the cursor before the first colon
+        assert!(!forest.contains(':'));
+
+        let cursor = Cursor::decode(&Cursor::encode(&forest, Uuid::new_v4(), &0.5f32)).unwrap();
+        assert!(cursor.key_for::<f32>(&forest).is_ok());
+        assert!(cursor.key_for::<f32>(&cabin).is_err());
+    }
+}
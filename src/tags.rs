@@ -0,0 +1,104 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::auth;
+use crate::models::Publicity;
+use crate::models::ScanStatus;
+use crate::schema::objects;
+use crate::schema::tags;
+use crate::search::escape_like;
+use axum::extract::Query;
+use axum::extract::State;
+use axum::middleware;
+use axum::{Json, Router, routing::get};
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+
+const TAGS_ROUTE: &str = "/tags";
+const POPULAR_TAGS_ROUTE: &str = constcat::concat!(TAGS_ROUTE, "/popular");
+
+const DEFAULT_LIMIT: i64 = 10;
+const MAX_LIMIT: i64 = 50;
+
+// how tags are stored and searched for
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+#[derive(Serialize, Queryable)]
+pub struct TagCount {
+    pub tag: String,
+    // how many objects have the tag
+    pub count: i64,
+}
+
+#[derive(Deserialize)]
+pub struct TagsQuery {
+    #[serde(default)]
+    prefix: String,
+    limit: Option<i64>,
+}
+
+// only counts objects everyone can see
+async fn tag_counts(
+    state: &AppState,
+    prefix: &str,
+    limit: Option<i64>,
+) -> Result<Vec<TagCount>, ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    let mut query = tags::table
+        .inner_join(objects::table)
+        .group_by(tags::tag)
+        .select((tags::tag, count_star()))
+        .filter(objects::publicity.eq(Publicity::Public as i16))
+        .filter(objects::scan_status.ne(ScanStatus::Quarantined as i16))
+        .order((count_star().desc(), tags::tag.asc()))
+        .limit(limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT))
+        .into_boxed();
+
+    if state.hide_unverified {
+        query = query.filter(objects::verified.eq(true));
+    }
+    if !prefix.is_empty() {
+        query = query.filter(tags::tag.like(format!("{}%", escape_like(prefix))));
+    }
+
+    Ok(query.load::<TagCount>(&mut conn).await?)
+}
+
+// autocomplete, the most used tags starting with the prefix
+pub async fn get_tags(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TagsQuery>,
+) -> Result<Json<Vec<TagCount>>, ApiError> {
+    tag_counts(&state, &normalize_tag(&query.prefix), query.limit)
+        .await
+        .map(Json)
+}
+
+#[derive(Deserialize)]
+pub struct PopularTagsQuery {
+    limit: Option<i64>,
+}
+
+pub async fn get_popular_tags(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PopularTagsQuery>,
+) -> Result<Json<Vec<TagCount>>, ApiError> {
+    tag_counts(&state, "", query.limit).await.map(Json)
+}
+
+pub fn tags_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(TAGS_ROUTE, get(get_tags))
+        .route(POPULAR_TAGS_ROUTE, get(get_popular_tags))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
@@ -0,0 +1,145 @@
+use crate::ApiError;
+use crate::AppState;
+use crate::auth;
+use crate::models;
+use crate::models::*;
+use crate::objects::PackageAccess;
+use crate::objects::check_can_download;
+use crate::schema::object_uses;
+use crate::schema::objects;
+use axum::Extension;
+use axum::extract::Path;
+use axum::extract::State;
+use axum::middleware;
+use axum::{Router, routing::post};
+use diesel::insert_into;
+use diesel::prelude::*;
+use diesel::sql_types::Integer;
+use diesel_async::AsyncPgConnection;
+use diesel_async::RunQueryDsl;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use tracing::error;
+use uuid::Uuid;
+
+const OBJECT_USE_ROUTE: &str = "/{object_type}/{uuid}/use";
+
+const SECONDS_PER_DAY: u64 = 60 * 60 * 24;
+const ROLLUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const WEEK_DAYS: i32 = 7;
+
+pub fn today() -> i32 {
+    (SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        / SECONDS_PER_DAY) as i32
+}
+
+// each user counts once per object per day no matter how often they download or join it
+// creators testing their own objects dont count at all
+pub async fn record_use(
+    conn: &mut AsyncPgConnection,
+    object_id: Uuid,
+    creator: Uuid,
+    user_id: Uuid,
+    kind: UseKind,
+) -> QueryResult<()> {
+    if creator == user_id {
+        return Ok(());
+    }
+
+    insert_into(object_uses::table)
+        .values(ObjectUse {
+            object: object_id,
+            user: user_id,
+            day: today(),
+            kind: kind as i16,
+            created_at: SystemTime::now(),
+        })
+        .on_conflict_do_nothing()
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// recounts weekly_uses from the last 7 days of uses
+// only objects used in that window or with a count to clear are touched
+// anything older isnt needed anymore so its deleted
+async fn roll_up_uses(conn: &mut AsyncPgConnection) -> QueryResult<()> {
+    let cutoff = today() - WEEK_DAYS;
+
+    diesel::sql_query(
+        "UPDATE objects SET weekly_uses = COALESCE(counts.uses, 0) FROM (\
+            SELECT object AS id FROM object_uses WHERE day > $1 \
+            UNION SELECT id FROM objects WHERE weekly_uses <> 0\
+        ) AS changed \
+        LEFT JOIN (\
+            SELECT object, COUNT(*)::INTEGER AS uses FROM object_uses \
+            WHERE day > $1 GROUP BY object\
+        ) AS counts ON counts.object = changed.id \
+        WHERE objects.id = changed.id AND objects.weekly_uses <> COALESCE(counts.uses, 0)",
+    )
+    .bind::<Integer, _>(cutoff)
+    .execute(conn)
+    .await?;
+
+    diesel::delete(object_uses::table)
+        .filter(object_uses::day.le(cutoff))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+// runs forever, started once from main
+pub async fn usage_rollup_task(state: Arc<AppState>) {
+    let mut interval = tokio::time::interval(ROLLUP_INTERVAL);
+    loop {
+        interval.tick().await;
+        let mut conn = match state.pool.get().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                error!("failed to get a connection for the usage rollup: {:?}", err);
+                continue;
+            }
+        };
+        if let Err(err) = roll_up_uses(&mut conn).await {
+            error!("failed to roll up object uses: {:?}", err);
+        }
+    }
+}
+
+// called by clients when they join a world or load an object someone else is using
+pub async fn report_use(
+    State(state): State<Arc<AppState>>,
+    Path((object_type, object_id)): Path<(models::ObjectType, Uuid)>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<(), ApiError> {
+    let mut conn = state.pool.get().await?;
+
+    // only a use of something the user could have downloaded counts
+    let access = objects::table
+        .select(PackageAccess::as_select())
+        .filter(objects::id.eq(object_id))
+        .filter(objects::object_type.eq(object_type as i16))
+        .first::<PackageAccess>(&mut conn)
+        .await
+        .optional()?
+        .ok_or_else(ApiError::not_found)?;
+    check_can_download(&state, &mut conn, object_id, &access, user_id).await?;
+
+    record_use(&mut conn, object_id, access.creator, user_id, UseKind::Join).await?;
+    Ok(())
+}
+
+pub fn usage_router(app_state: Arc<AppState>) -> Router {
+    Router::new()
+        .route(OBJECT_USE_ROUTE, post(report_use))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            auth::check_auth,
+        ))
+        .with_state(app_state)
+}
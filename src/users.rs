@@ -3,11 +3,13 @@ use crate::AppState;
 use crate::ErrorCode;
 use crate::ErrorInfo;
 use crate::auth;
+use crate::creators::object_counts;
 use crate::email::EmailType;
 use crate::email::check_email;
 use crate::email::send_email;
 use crate::hash::hash_password;
 use crate::models::*;
+use crate::objects::storage_used;
 use crate::schema::unverified_users;
 use crate::schema::users;
 use axum::Extension;
@@ -24,6 +26,8 @@ use diesel_async::scoped_futures::ScopedFutureExt;
 use diesel_async::{AsyncConnection, RunQueryDsl};
 use rand_core::TryRngCore;
 use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 use std::time::SystemTime;
@@ -33,6 +37,7 @@ use uuid::Uuid;
 const USERS_ROUTE: &str = "/user";
 const USER_ID_ROUTE: &str = constcat::concat!(USERS_ROUTE, "/{usr_id}");
 const USER_EMAIL_VERIFY_ROUTE: &str = constcat::concat!(USER_ID_ROUTE, "/verify/{token}");
+const USER_STORAGE_ROUTE: &str = constcat::concat!(USER_ID_ROUTE, "/storage");
 
 #[derive(Deserialize)]
 pub struct SignUpRequest {
@@ -146,9 +151,18 @@ pub async fn sign_up(
     .await
 }
 
+// a user along with how many objects theyve published
+#[derive(Serialize)]
+pub struct UserProfile<T> {
+    #[serde(flatten)]
+    pub user: T,
+    // keyed by the search field of each object type, e.g. worlds
+    pub object_counts: BTreeMap<&'static str, i64>,
+}
+
 pub enum GetUserResult {
-    PublicUser(Json<PublicUserInfo>),
-    User(Json<User>),
+    PublicUser(Json<UserProfile<PublicUserInfo>>),
+    User(Json<UserProfile<User>>),
 }
 
 impl IntoResponse for GetUserResult {
@@ -174,13 +188,20 @@ pub async fn get_user(
         .await
         .optional()
     {
+        let object_counts = object_counts(&state, &mut conn, user.id, user_id).await?;
         if user_id == user.id {
             // todo: this is kinda jank
             user.homeworld = Some(user.homeworld.unwrap_or(Uuid::nil()));
             user.avatar = Some(user.avatar.unwrap_or(Uuid::from_u64_pair(0, 1))); // yeah thats not hacky at all
-            return Ok(GetUserResult::User(Json(user)));
+            return Ok(GetUserResult::User(Json(UserProfile {
+                user,
+                object_counts,
+            })));
         } else {
-            return Ok(GetUserResult::PublicUser(Json(user.into())));
+            return Ok(GetUserResult::PublicUser(Json(UserProfile {
+                user: user.into(),
+                object_counts,
+            })));
         }
     }
     Err(ApiError::WithResponse(
@@ -192,6 +213,50 @@ pub async fn get_user(
     ))
 }
 
+#[derive(Serialize)]
+pub struct StorageInfo {
+    pub used: i64,
+    pub quota: i64,
+}
+
+pub async fn get_user_storage(
+    State(state): State<Arc<AppState>>,
+    Path(usr_id): Path<Uuid>,
+    Extension(user_id): Extension<Uuid>,
+) -> Result<Json<StorageInfo>, ApiError> {
+    if usr_id != user_id {
+        return Err(ApiError::WithResponse(
+            StatusCode::FORBIDDEN,
+            Json(ErrorInfo {
+                error_code: ErrorCode::InsufficientPermissions,
+                error_message: Some("You can only view your own storage usage.".to_owned()),
+            }),
+        ));
+    }
+
+    let mut conn = state.pool.get().await?;
+
+    if let Some(trust) = users::table
+        .select(users::trust)
+        .filter(users::id.eq(usr_id))
+        .first::<i32>(&mut conn)
+        .await
+        .optional()?
+    {
+        return Ok(Json(StorageInfo {
+            used: storage_used(usr_id, &mut conn).await?,
+            quota: state.upload_limits.storage_quota(trust),
+        }));
+    }
+    Err(ApiError::WithResponse(
+        StatusCode::NOT_FOUND,
+        Json(ErrorInfo {
+            error_code: ErrorCode::DosentExist,
+            error_message: None,
+        }),
+    ))
+}
+
 pub async fn verify_email(
     State(state): State<Arc<AppState>>,
     Path((usr_id, token)): Path<(Uuid, String)>,
@@ -288,6 +353,13 @@ pub fn users_router(app_state: Arc<AppState>) -> Router {
                 auth::check_auth,
             )),
         )
+        .route(
+            USER_STORAGE_ROUTE,
+            get(get_user_storage).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                auth::check_auth,
+            )),
+        )
         .route(USER_EMAIL_VERIFY_ROUTE, get(verify_email))
         .with_state(app_state)
 }